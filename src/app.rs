@@ -0,0 +1,210 @@
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::config::{Overrides, SharedConfig};
+use crate::control::Control;
+use crate::events::{AppEvent, Events};
+use crate::history::History;
+use crate::hotkeys::{HotkeyListener, ListenerEvent, ListenerProxy};
+use crate::instance::InstanceLock;
+use crate::paths::Paths;
+use crate::pipeline::Pipeline;
+use crate::recordings::RecordingCache;
+use crate::rules::{Rules, SharedRules};
+use crate::undo::UndoTracker;
+use crate::{audio, reload};
+use log::*;
+
+/// How long to wait for transcriptions in flight when shutting down
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A running Talky: the recorder, transcription pipeline and hotkey listener, plus the
+/// handles front-ends use to watch and drive them
+pub struct App {
+    pub control: Arc<Control>,
+    pub app_config: SharedConfig,
+    pub events: Events,
+    pub history: Arc<History>,
+    pub undo: Arc<UndoTracker>,
+    /// Sends commands such as Toggle to the hotkey listener, which owns the recording state
+    pub listener: ListenerProxy,
+    hotkey_listener: HotkeyListener,
+    recording_thread: JoinHandle<Result<()>>,
+    pipeline: Pipeline,
+    cache: RecordingCache,
+    paths: Paths,
+    _lock: InstanceLock,
+}
+
+impl App {
+    /// Loads the config, registers the hotkeys and starts recording and transcribing
+    /// threads. Hotkeys aren't handled until `run` is called.
+    pub fn start(paths: &Paths, overrides: &Overrides) -> Result<Self> {
+        // Checked first, since a second copy can't register the same hotkeys
+        let lock = InstanceLock::acquire(&paths.lock_file())?;
+
+        let (config, api_key) = crate::load_config(paths, overrides)?;
+
+        let recordings_dir = paths.recordings_dir();
+
+        cleanup_wav_files(&recordings_dir)?;
+
+        for binding in config.hotkey_bindings() {
+            info!("Press {} to {}", binding.hotkey, binding.actions);
+        }
+
+        // Shared recording state
+        let control = Arc::new(Control::new());
+        let undo = Arc::new(UndoTracker::new());
+        let events = Events::new();
+
+        let workers = config.transcription.workers;
+
+        // Create shared configuration and API key
+        let app_config: SharedConfig = Arc::new(ArcSwap::from_pointee((config, api_key)));
+        let rules: SharedRules = Arc::new(ArcSwap::from_pointee(Rules::load(&paths.rules_file)?));
+
+        let cache = RecordingCache::new(paths.recent_dir());
+        let history = Arc::new(History::new(paths.history_file()));
+        let pipeline = Pipeline::start(workers, cache.clone(), undo.clone(), history.clone(), rules.clone(), events.clone());
+
+        // Initialize hotkey listener
+        let config = app_config.load();
+        let mut hotkey_listener = HotkeyListener::new(&config.0.listener, control.clone(), app_config.clone(), undo.clone(), pipeline.clone())?;
+        hotkey_listener.setup_hotkeys(&config.0.hotkey_bindings())?;
+
+        // The first Ctrl+C (or SIGTERM) shuts down cleanly, a second one exits right away
+        let proxy = hotkey_listener.proxy();
+        let interrupted = AtomicBool::new(false);
+        ctrlc::set_handler(move || {
+            if interrupted.swap(true, Ordering::SeqCst) {
+                crate::tui::restore_terminal();
+                warn!("Exiting without waiting for transcriptions");
+                std::process::exit(130);
+            }
+            info!("Shutting down, press Ctrl+C again to exit right away");
+            let _ = proxy.send_event(ListenerEvent::Shutdown);
+        })?;
+
+        // Reload the config when config.yaml or apikey.yaml change
+        if let Err(e) = reload::watch(paths.clone(), overrides.clone(), app_config.clone(), rules.clone(), hotkey_listener.proxy()) {
+            warn!("Config changes won't be picked up until restart: {:?}", e);
+        }
+
+        // Let `talky ctl` drive recording through the control socket
+        #[cfg(unix)]
+        {
+            let server = crate::ipc::Server {
+                paths: paths.clone(),
+                overrides: overrides.clone(),
+                control: control.clone(),
+                app_config: app_config.clone(),
+                rules: rules.clone(),
+                listener: hotkey_listener.proxy(),
+                pipeline: pipeline.clone(),
+            };
+            if let Err(e) = server.start() {
+                warn!("`talky ctl` won't work: {:#}", e);
+            }
+        }
+
+        // Desktop environments and scripts can also use the session bus
+        #[cfg(target_os = "linux")]
+        if let Err(e) = crate::dbus::serve(control.clone(), app_config.clone(), hotkey_listener.proxy(), events.subscribe()) {
+            warn!("D-Bus interface not available: {:#}", e);
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Err(e) = crate::notifications::show(app_config.clone(), events.subscribe()) {
+            warn!("Desktop notifications not available: {:#}", e);
+        }
+
+        crate::cues::play(control.clone(), app_config.clone(), events.subscribe());
+
+        // Start recording management in a separate thread
+        let control_clone = control.clone();
+        let app_config_clone = app_config.clone();
+        let events_clone = events.clone();
+        let recording_pipeline = pipeline.clone();
+        let recording_thread = thread::spawn(move || -> Result<()> {
+            // The recording thread polls `control` for when to start and stop
+            let result = audio::record_audio(&recordings_dir, control_clone, app_config_clone, recording_pipeline, events_clone.clone());
+            if let Err(e) = &result {
+                events_clone.emit(AppEvent::Error { message: format!("Recording stopped working: {:#}", e) });
+            }
+            result
+        });
+
+        Ok(Self {
+            control,
+            app_config,
+            events,
+            history,
+            undo,
+            listener: hotkey_listener.proxy(),
+            hotkey_listener,
+            recording_thread,
+            pipeline,
+            cache,
+            paths: paths.clone(),
+            _lock: lock,
+        })
+    }
+
+    /// Listens for hotkeys on this thread until told to shut down, then waits for the
+    /// last recording and the transcriptions in flight
+    pub fn run(self) -> Result<()> {
+        debug!("Starting hotkey listener...");
+        self.hotkey_listener.run()?;
+
+        // The listener has told the recording thread to finish up
+        match self.recording_thread.join() {
+            Ok(Ok(())) => {},
+            Ok(Err(e)) => error!("Recording error: {:?}", e),
+            Err(_) => error!("The recording thread panicked"),
+        }
+
+        // Recordings that weren't transcribed in time are kept for `talky retranscribe`
+        for path in self.pipeline.wait(SHUTDOWN_TIMEOUT) {
+            match self.cache.add(&path) {
+                Ok(cached) => warn!("Transcription didn't finish, the recording is kept at {}", cached.display()),
+                Err(e) => error!("Failed to keep an unfinished recording: {:?}", e),
+            }
+        }
+
+        #[cfg(unix)]
+        let _ = fs::remove_file(self.paths.socket_file());
+
+        info!("Stopped");
+        Ok(())
+    }
+}
+
+// Deletes leftover wav recordings in the given directory
+fn cleanup_wav_files(dir: &Path) -> Result<()> {
+    let entries = fs::read_dir(dir)?;
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+
+        // Check if it's a file
+        if path.is_file() {
+            // Check if it's a .wav file
+            if let Some(file_name) = path.file_name() {
+                let file_name_str = file_name.to_string_lossy();
+                if file_name_str.ends_with(".wav") {
+                    debug!("Removing file: {}", path.display());
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -2,17 +2,18 @@ use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{Sample, SampleFormat};
 use std::fs::File;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::thread::{self};
-use std::time::Duration;
-use std::path::Path;
-use crate::speech;
-use crate::clipboard;
-use crate::config::{Config, ApiKeyConfig};
+use std::io::BufWriter;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use crate::config::SharedConfig;
+use crate::control::Control;
+use crate::events::{AppEvent, Events};
+use crate::pipeline::Pipeline;
 use log::*;
 
-pub fn record_audio(base_path: &str, recording_flag: Arc<AtomicBool>, app_config: Arc<(Config, ApiKeyConfig)>) -> Result<()> {
+pub fn record_audio(recordings_dir: &Path, control: Arc<Control>, app_config: SharedConfig, pipeline: Pipeline, events: Events) -> Result<()> {
     // Get the default host
     let host = cpal::default_host();
     
@@ -28,53 +29,53 @@ pub fn record_audio(base_path: &str, recording_flag: Arc<AtomicBool>, app_config
     
     debug!("Default input config: {:?}", input_config);
     
+    let spec = hound::WavSpec {
+        channels: input_config.channels(),
+        sample_rate: input_config.sample_rate().0,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
     let mut stream_active = false;
-    let mut writer_opt: Option<Arc<std::sync::Mutex<hound::WavWriter<std::io::BufWriter<File>>>>> = None;
+    let mut recorder_opt: Option<Arc<Mutex<Recorder>>> = None;
     let mut stream_opt: Option<cpal::Stream> = None;
-    let mut current_file_path: Option<String> = None;
+    let mut current_profile: Option<String> = None;
     let mut file_count = 1;
+    let mut next_file = || {
+        let file_path = recordings_dir.join(format!("recording_{}.wav", file_count));
+        file_count += 1;
+        file_path
+    };
     
     debug!("Waiting for hotkey to start recording...");
-    debug!("Current recording flag state: {}", recording_flag.load(Ordering::SeqCst));
+    debug!("Current recording flag state: {}", control.is_recording());
     
     // Main processing loop that monitors the recording flag
-    let base_path = Path::new(base_path);
-    let base_stem = base_path.file_stem().unwrap_or_default().to_string_lossy();
-    let base_ext = base_path.extension().unwrap_or_default().to_string_lossy();
-    
     loop { 
-        let should_record = recording_flag.load(Ordering::SeqCst);
+        let should_record = control.is_recording();
         
         // Start recording if flag is true and we're not already recording
         if should_record && !stream_active {
             debug!("Starting recording");
             
-            // TODO delete all files on startup 
-            let file_path = if base_ext.is_empty() {
-                format!("{}_{}.wav", base_stem, file_count)
-            } else {
-                format!("{}_{}.{}", base_stem, file_count, base_ext)
-            };
+            let file_path = next_file();
+            debug!("Recording to file: {}", file_path.display());
+            current_profile = Some(control.profile());
 
-            file_count += 1;
-            
-            debug!("Recording to file: {}", file_path);
-            current_file_path = Some(file_path.clone());
-            
-            // Create WAV writer with timestamp in filename
-            let spec = hound::WavSpec {
-                channels: input_config.channels(),
-                sample_rate: input_config.sample_rate().0,
-                bits_per_sample: 16,
-                sample_format: hound::SampleFormat::Int,
-            };
-            
-            let writer = Arc::new(std::sync::Mutex::new(
-                hound::WavWriter::create(&file_path, spec)
-                    .context("Failed to create WAV writer")?
-            ));
-            
-            writer_opt = Some(writer.clone());
+            let hands_free = control.is_hands_free().then(|| {
+                let config = &app_config.load().0.hands_free;
+                HandsFree {
+                    threshold: config.threshold,
+                    silence: Duration::from_millis(config.silence_ms),
+                    next: None,
+                    finished: Vec::new(),
+                }
+            });
+            let recorder = Arc::new(Mutex::new(Recorder {
+                current: Segment::create(file_path, spec)?,
+                hands_free,
+            }));
+            recorder_opt = Some(recorder.clone());
             
             // Create error callback
             let err_fn = |err| error!("An error occurred on the input audio stream: {}", err);
@@ -82,28 +83,31 @@ pub fn record_audio(base_path: &str, recording_flag: Arc<AtomicBool>, app_config
             // Build the input stream
             let stream = match input_config.sample_format() {
                 SampleFormat::F32 => {
-                    let writer = writer.clone();
+                    let recorder = recorder.clone();
+                    let control = control.clone();
                     device.build_input_stream(
                         &input_config.config(),
-                        move |data: &[f32], _: &_| write_input_data::<f32, i16>(data, &writer),
+                        move |data: &[f32], _: &_| write_input_data::<f32, i16>(data, &recorder, &control),
                         err_fn,
                         None
                     )
                 },
                 SampleFormat::I16 => {
-                    let writer = writer.clone();
+                    let recorder = recorder.clone();
+                    let control = control.clone();
                     device.build_input_stream(
                         &input_config.config(),
-                        move |data: &[i16], _: &_| write_input_data::<i16, i16>(data, &writer),
+                        move |data: &[i16], _: &_| write_input_data::<i16, i16>(data, &recorder, &control),
                         err_fn,
                         None
                     )
                 },
                 SampleFormat::U16 => {
-                    let writer = writer.clone();
+                    let recorder = recorder.clone();
+                    let control = control.clone();
                     device.build_input_stream(
                         &input_config.config(),
-                        move |data: &[u16], _: &_| write_input_data::<u16, i16>(data, &writer),
+                        move |data: &[u16], _: &_| write_input_data::<u16, i16>(data, &recorder, &control),
                         err_fn,
                         None
                     )
@@ -116,6 +120,7 @@ pub fn record_audio(base_path: &str, recording_flag: Arc<AtomicBool>, app_config
             stream_opt = Some(stream);
             stream_active = true;
             debug!("Audio stream activated successfully");
+            events.emit(AppEvent::RecordingStarted { profile: current_profile.clone().unwrap_or_default() });
         } 
         // Stop recording if flag is false and we are currently recording
         else if !should_record && stream_active {
@@ -124,72 +129,257 @@ pub fn record_audio(base_path: &str, recording_flag: Arc<AtomicBool>, app_config
             // Stop and drop the stream first
             if let Some(stream) = stream_opt.take() {
                 debug!("Stopping audio stream");
-                // Explicitly stop the stream before dropping TODO why?
+                // Stop the input before dropping the stream, so no more samples arrive
                 if let Err(e) = stream.pause() {
                     error!("Error stopping stream: {:?}", e);
                 }
                 drop(stream);
             }
 
-            // Get ownership of the WAV writer so that we can finalize it
-
-            let Some(writer_arc) = writer_opt.take() else {
-                return Err(anyhow::anyhow!("Writer arc is empty"));
+            // Get ownership of the recorder so that we can finalize its files
+            let Some(recorder_arc) = recorder_opt.take() else {
+                return Err(anyhow::anyhow!("Recorder arc is empty"));
             };
 
-            let Ok(mutex) = Arc::try_unwrap(writer_arc) else {
-                return Err(anyhow::anyhow!("Failed to get mutex of the wav writer"));
+            let Ok(mutex) = Arc::try_unwrap(recorder_arc) else {
+                return Err(anyhow::anyhow!("Failed to get mutex of the recorder"));
             };
 
-            let Ok(writer) = mutex.into_inner() else {
-                return Err(anyhow::anyhow!("Failed to get WAV writer"));
+            let Ok(recorder) = mutex.into_inner() else {
+                return Err(anyhow::anyhow!("Failed to get the recorder"));
             };
 
-            writer.finalize()?;
+            let profile_name = current_profile.take().unwrap_or_default();
+            let Recorder { current, hands_free } = recorder;
+            let nothing_said = hands_free.is_some() && current.last_heard.is_none();
 
-            // Transcribe the audio in a separate thread
-
-            let Some(file_path_clone) = current_file_path.clone() else {
-                return Err(anyhow::anyhow!("current_file_path is empty"));
-            };
-
-            let app_config_clone = app_config.clone();
-
-            thread::spawn(move || {
-                match speech::transcribe_audio(&file_path_clone, &app_config_clone) {
-                    Ok(text) => {
-                        info!("Transcription: {}", text);
-                        
-                        if let Err(e) = clipboard::paste_text(&text) {
-                            error!("Failed to paste text: {:?}", e);
-                        } 
-                    },
-                    Err(e) => error!("Failed to transcribe audio: {:?}", e),
+            // Hands-free segments that were split off since the last check
+            if let Some(hands_free) = hands_free {
+                for segment in hands_free.finished {
+                    let file_path = segment.finish()?;
+                    pipeline.submit(file_path, profile_name.clone(), app_config.load_full());
                 }
-            });
+                if let Some(next) = hands_free.next {
+                    next.discard();
+                }
+            }
+
+            if control.take_cancelled() {
+                // Nothing is sent to the API for a cancelled recording
+                current.discard();
+                info!("Recording cancelled, audio discarded");
+                events.emit(AppEvent::RecordingCancelled);
+            } else if nothing_said {
+                // The end of a hands-free recording, after the last thing that was said
+                current.discard();
+                debug!("Nothing was said, audio discarded");
+                events.emit(AppEvent::RecordingStopped);
+            } else {
+                let file_path = current.finish()?;
+                events.emit(AppEvent::RecordingStopped);
+                // Transcribe the audio on the worker pool, using the config as it is now
+                // even if it is reloaded while transcribing
+                pipeline.submit(file_path, profile_name, app_config.load_full());
+            }
                 
             stream_active = false;
-            current_file_path = None;
+            control.set_level(0.0);
             debug!("Recording stopped and saved.");
         }
+        // Cancelled before the stream had started, so there's nothing to throw away
+        else if !stream_active && !control.is_recording() && control.take_cancelled() {
+            info!("Recording cancelled before it started");
+            events.emit(AppEvent::RecordingCancelled);
+        }
+        // A hands-free recording goes on, queue the segments the stream split off and
+        // have the next one ready. This doesn't count as stopping and starting, so
+        // there are no cues or notifications for it.
+        else if let Some(recorder) = recorder_opt.as_ref() {
+            let (finished, needs_next) = match &mut recorder.lock().unwrap().hands_free {
+                Some(hands_free) => (std::mem::take(&mut hands_free.finished), hands_free.next.is_none()),
+                None => (Vec::new(), false),
+            };
+            for segment in finished {
+                debug!("Speaker paused, transcribing {}", segment.path.display());
+                let file_path = segment.finish()?;
+                pipeline.submit(file_path, current_profile.clone().unwrap_or_default(), app_config.load_full());
+            }
+            if needs_next {
+                let next = Segment::create(next_file(), spec)?;
+                if let Some(hands_free) = &mut recorder.lock().unwrap().hands_free {
+                    hands_free.next = Some(next);
+                }
+            }
+        }
+
+        // The last recording has been finished and queued
+        if !stream_active && control.is_shut_down() {
+            debug!("Recording thread stopped");
+            return Ok(());
+        }
         
         // Check every 100ms to avoid busy-waiting
         thread::sleep(Duration::from_millis(100));
     }
 }
 
-fn write_input_data<T, U>(input: &[T], writer: &Arc<std::sync::Mutex<hound::WavWriter<std::io::BufWriter<File>>>>)
+fn write_input_data<T, U>(input: &[T], recorder: &Mutex<Recorder>, control: &Control)
 where
     T: Sample,
     U: Sample + hound::Sample + cpal::FromSample<T>,
+    f32: cpal::FromSample<T>,
 {
-    if let Ok(mut guard) = writer.try_lock() {
-        for &sample in input.iter() {
-            let sample: U = sample.to_sample();
-            if let Err(e) = guard.write_sample(sample) {
-                error!("Error writing audio sample: {:?}", e);
-                return;
+    // A paused recording just leaves the input out
+    if control.is_paused() {
+        control.set_level(0.0);
+        return;
+    }
+
+    // While the start cue plays, record silence instead so the cue isn't transcribed
+    let muted = control.is_muted();
+
+    // Peak level of this buffer, for the level meter
+    let level = match muted {
+        true => 0.0,
+        false => input.iter().fold(0.0f32, |peak, &sample| peak.max(sample.to_sample::<f32>().abs())),
+    };
+    control.set_level(level.min(1.0));
+
+    let mut recorder = recorder.lock().unwrap();
+    recorder.listen(level, Instant::now());
+    for &sample in input.iter() {
+        let sample: U = match muted {
+            true => T::EQUILIBRIUM.to_sample(),
+            false => sample.to_sample(),
+        };
+        if let Err(e) = recorder.current.writer.write_sample(sample) {
+            error!("Error writing audio sample: {:?}", e);
+            return;
+        }
+    }
+}
+
+/// What the input stream writes to. A hands-free recording is split into segments
+/// here, in the stream callback, wherever the speaker pauses. The stream keeps running
+/// across the split, so nothing said around it is lost.
+struct Recorder {
+    current: Segment,
+    hands_free: Option<HandsFree>,
+}
+
+struct HandsFree {
+    /// Input above this level counts as speech. A plain level threshold is enough to
+    /// tell a pause from speech in a quiet room.
+    threshold: f32,
+    /// A pause this long after speech ends the segment
+    silence: Duration,
+    /// Takes over from the current segment at the next pause. The recording thread
+    /// creates it ahead of time, so the callback doesn't have to.
+    next: Option<Segment>,
+    /// Segments that were split off, for the recording thread to finish and queue
+    finished: Vec<Segment>,
+}
+
+impl Recorder {
+    /// Tracks speech in a hands-free recording, and moves on to the next segment when
+    /// the speaker pauses
+    fn listen(&mut self, level: f32, now: Instant) {
+        let Some(hands_free) = &mut self.hands_free else {
+            return;
+        };
+        if level >= hands_free.threshold {
+            self.current.last_heard = Some(now);
+        }
+        let paused_speaking = self.current.last_heard.is_some_and(|at| now - at >= hands_free.silence);
+        if paused_speaking {
+            if let Some(next) = hands_free.next.take() {
+                hands_free.finished.push(std::mem::replace(&mut self.current, next));
             }
         }
     }
 }
+
+/// A WAV file being recorded
+struct Segment {
+    path: PathBuf,
+    writer: hound::WavWriter<BufWriter<File>>,
+    /// When there was last speech in it, for hands-free recordings
+    last_heard: Option<Instant>,
+}
+
+impl Segment {
+    fn create(path: PathBuf, spec: hound::WavSpec) -> Result<Self> {
+        let writer = hound::WavWriter::create(&path, spec)
+            .context("Failed to create WAV writer")?;
+        Ok(Self { path, writer, last_heard: None })
+    }
+
+    /// Finalizes the file and returns its path
+    fn finish(self) -> Result<PathBuf> {
+        self.writer.finalize()?;
+        Ok(self.path)
+    }
+
+    fn discard(self) {
+        drop(self.writer);
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            error!("Failed to delete file {}: {:?}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: hound::WavSpec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 16000,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+
+    fn segment(dir: &Path, name: &str) -> Segment {
+        Segment::create(dir.join(name), SPEC).unwrap()
+    }
+
+    #[test]
+    fn hands_free_splits_at_pauses() {
+        let dir = std::env::temp_dir().join(format!("talky-audio-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut recorder = Recorder {
+            current: segment(&dir, "1.wav"),
+            hands_free: Some(HandsFree {
+                threshold: 0.1,
+                silence: Duration::from_millis(500),
+                next: Some(segment(&dir, "2.wav")),
+                finished: Vec::new(),
+            }),
+        };
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // Quiet before anything is said doesn't split
+        recorder.listen(0.0, at(0));
+        recorder.listen(0.0, at(1000));
+        assert_eq!(recorder.current.path, dir.join("1.wav"));
+
+        recorder.listen(0.5, at(1100));
+        recorder.listen(0.0, at(1400));
+        assert_eq!(recorder.current.path, dir.join("1.wav"));
+        recorder.listen(0.0, at(1600));
+        assert_eq!(recorder.current.path, dir.join("2.wav"));
+        assert_eq!(recorder.current.last_heard, None);
+
+        let hands_free = recorder.hands_free.as_mut().unwrap();
+        assert_eq!(hands_free.finished.len(), 1);
+        assert!(hands_free.next.is_none());
+
+        // Without the next segment ready, the current one carries on
+        recorder.listen(0.5, at(1700));
+        recorder.listen(0.0, at(2500));
+        assert_eq!(recorder.current.path, dir.join("2.wav"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,216 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Type using your voice
+#[derive(Debug, Parser)]
+#[command(name = "talky", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// Config file to use instead of the one in the config directory
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Override a config value, e.g. `--set api.model=whisper-1`. Can be repeated.
+    /// Environment variables such as TALKY_API_MODEL work the same way.
+    #[arg(short, long = "set", global = true, value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+
+    /// More log output, repeat for even more
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Less log output, repeat for even less
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub quiet: u8,
+
+    /// Also write log output to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Listen for the hotkey and transcribe recordings (the default)
+    Run,
+
+    /// Run with a dashboard showing the recording state, queue and history
+    Tui,
+
+    /// Transcribe an audio file and print the text
+    Transcribe {
+        /// Audio file to transcribe
+        file: PathBuf,
+
+        /// Profile to transcribe with
+        #[arg(short, long, default_value = crate::config::DEFAULT_PROFILE)]
+        profile: String,
+    },
+
+    /// Transcribe a recent recording again and print the text. `talky ctl retranscribe`
+    /// outputs it instead.
+    Retranscribe {
+        /// Profile to transcribe with
+        #[arg(short, long, default_value = crate::config::DEFAULT_PROFILE)]
+        profile: String,
+
+        /// Which recording, 1 is the latest
+        #[arg(short, long, default_value_t = 1)]
+        nth: usize,
+    },
+
+    /// Look through past transcriptions
+    #[command(subcommand)]
+    History(HistoryCommand),
+
+    /// Control the running Talky, for compositor shortcuts and scripts
+    #[command(subcommand)]
+    Ctl(CtlCommand),
+
+    /// List audio input devices
+    Devices,
+
+    /// Manage the config file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Check that everything needed to run Talky works
+    Doctor,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Write a default config file
+    Init {
+        /// Overwrite an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Validate the config file and report every problem
+    Check,
+
+    /// Print the effective config, after overrides, and where it was loaded from
+    Show,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CtlCommand {
+    /// Start recording, with the selected profile unless one is given
+    Start { profile: Option<String> },
+
+    /// Stop recording and transcribe
+    Stop,
+
+    /// Start or stop recording
+    Toggle { profile: Option<String> },
+
+    /// Stop recording and throw the recording away
+    Cancel,
+
+    /// Show whether Talky is recording and with which profile
+    Status,
+
+    /// Select the profile used when none is given
+    SwitchProfile { profile: String },
+
+    /// Reload config.yaml and apikey.yaml
+    Reload,
+
+    /// Transcribe the last recording again and output the text like the
+    /// retranscribe hotkey, with retranscribe.profile unless a profile is given
+    Retranscribe {
+        /// Profile to transcribe with
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+}
+
+impl CtlCommand {
+    /// The command as sent over the control socket
+    pub fn to_line(&self) -> String {
+        match self {
+            CtlCommand::Start { profile: Some(profile) } => format!("start {}", profile),
+            CtlCommand::Start { profile: None } => "start".to_string(),
+            CtlCommand::Stop => "stop".to_string(),
+            CtlCommand::Toggle { profile: Some(profile) } => format!("toggle {}", profile),
+            CtlCommand::Toggle { profile: None } => "toggle".to_string(),
+            CtlCommand::Cancel => "cancel".to_string(),
+            CtlCommand::Status => "status".to_string(),
+            CtlCommand::SwitchProfile { profile } => format!("switch-profile {}", profile),
+            CtlCommand::Reload => "reload".to_string(),
+            CtlCommand::Retranscribe { profile: Some(profile) } => format!("retranscribe {}", profile),
+            CtlCommand::Retranscribe { profile: None } => "retranscribe".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum HistoryCommand {
+    /// Show the latest transcriptions
+    List {
+        /// How many to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Only show transcriptions made with this profile
+        #[arg(short, long)]
+        profile: Option<String>,
+    },
+
+    /// Find transcriptions containing every word of the query
+    Search {
+        query: Vec<String>,
+
+        /// How many to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Copy a transcription to the clipboard
+    Copy {
+        /// Id from `talky history list`, the latest if not given
+        id: Option<u64>,
+    },
+
+    /// Write the history as Markdown, CSV or JSON
+    Export {
+        #[arg(short, long, value_enum, default_value = "markdown")]
+        format: crate::history::ExportFormat,
+
+        /// File to write to instead of stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+
+    /// Delete transcriptions older than the given age
+    Prune {
+        /// Age such as 30d, 12h or 45m
+        #[arg(long, value_name = "AGE")]
+        older_than: String,
+    },
+}
+
+impl GlobalArgs {
+    /// Log level from the number of -v and -q flags, starting at Info
+    pub fn log_level(&self) -> log::LevelFilter {
+        const LEVELS: [log::LevelFilter; 6] = [
+            log::LevelFilter::Off,
+            log::LevelFilter::Error,
+            log::LevelFilter::Warn,
+            log::LevelFilter::Info,
+            log::LevelFilter::Debug,
+            log::LevelFilter::Trace,
+        ];
+
+        let index = (3 + self.verbose as i32 - self.quiet as i32).clamp(0, LEVELS.len() as i32 - 1);
+        LEVELS[index as usize]
+    }
+}
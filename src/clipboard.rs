@@ -1,45 +1,71 @@
 use anyhow::Result;
-use clipboard_rs::{Clipboard, ClipboardContext};
-use enigo::{Enigo, Key, Keyboard, Direction};
+use clipboard_rs::{Clipboard, ClipboardContent, ClipboardContext, ContentFormat};
+use enigo::Enigo;
 use std::thread;
 use std::time::Duration;
+use crate::keys::Chord;
+use log::*;
+
+/// Formats saved before pasting, everything clipboard-rs can read
+const SNAPSHOT_FORMATS: [ContentFormat; 5] = [
+    ContentFormat::Text,
+    ContentFormat::Rtf,
+    ContentFormat::Html,
+    ContentFormat::Image,
+    ContentFormat::Files,
+];
+
+fn clipboard_context() -> Result<ClipboardContext> {
+    ClipboardContext::new().map_err(|e| anyhow::anyhow!("Failed to open clipboard: {}", e))
+}
 
 /// Copies text to the clipboard
 pub fn copy_to_clipboard(text: &str) -> Result<()> {
-    let ctx = ClipboardContext::new().unwrap();
+    let ctx = clipboard_context()?;
     ctx.set_text(text.to_string()).map_err(|e| anyhow::anyhow!("Failed to set clipboard text: {}", e))
 }
 
-/// Pastes the current clipboard content using keyboard emulation
-pub fn paste_clipboard() -> Result<()> {
-    // todo better error handling
-    let mut enigo = Enigo::new(&enigo::Settings::default())?;
-    
-    // Small delay to ensure applications are ready
-    thread::sleep(Duration::from_millis(500));
-    
-    // Execute Ctrl+V (or Command+V on macOS)
-    #[cfg(target_os = "macos")]
-    {
-        enigo.key_down(Key::Meta);
-        enigo.key_click(Key::Layout('v'));
-        enigo.key_up(Key::Meta);
+/// Clipboard contents saved before a paste so they can be put back afterwards
+pub struct ClipboardSnapshot {
+    contents: Vec<ClipboardContent>,
+}
+
+impl ClipboardSnapshot {
+    /// Saves the current clipboard contents in every format we can read
+    pub fn take() -> Result<Self> {
+        let ctx = clipboard_context()?;
+        let contents = ctx.get(&SNAPSHOT_FORMATS)
+            .map_err(|e| anyhow::anyhow!("Failed to read clipboard: {}", e))?;
+        Ok(Self { contents })
     }
-    
-    #[cfg(not(target_os = "macos"))]
-    {
-        enigo.key(Key::Control, Direction::Press)?;
-        enigo.key(Key::V, Direction::Click)?;
-        enigo.key(Key::Control, Direction::Release)?;
+
+    /// Puts the saved contents back, unless the clipboard no longer holds `pasted`,
+    /// which means something else was copied in the meantime
+    pub fn restore(self, pasted: &str) -> Result<()> {
+        let ctx = clipboard_context()?;
+        if ctx.get_text().ok().as_deref() != Some(pasted) {
+            debug!("Clipboard changed since pasting, not restoring it");
+            return Ok(());
+        }
+
+        if self.contents.is_empty() {
+            ctx.clear()
+        } else {
+            ctx.set(self.contents)
+        }.map_err(|e| anyhow::anyhow!("Failed to restore clipboard: {}", e))?;
+
+        debug!("Restored previous clipboard contents");
+        Ok(())
     }
-    
-    Ok(())
 }
 
-/// Copies text to clipboard and then pastes it
-pub fn paste_text(text: &str) -> Result<()> {
-    copy_to_clipboard(text)?;
-    paste_clipboard()?;
+/// Pastes the current clipboard content by pressing the paste keys
+pub fn paste_clipboard(delay_ms: u64, keys: &Chord) -> Result<()> {
+    // todo better error handling
+    let mut enigo = Enigo::new(&enigo::Settings::default())?;
+    
+    // Small delay to ensure applications are ready
+    thread::sleep(Duration::from_millis(delay_ms));
     
-    Ok(())
+    keys.send(&mut enigo)
 }
@@ -0,0 +1,334 @@
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait};
+use global_hotkey::{hotkey::HotKey, GlobalHotKeyManager};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use crate::config::{Config, ApiKeyConfig, HotkeyBackend, Overrides};
+use crate::cli::{CtlCommand, HistoryCommand};
+use crate::history::{self, Entry, History};
+use crate::paths::Paths;
+use crate::recordings::RecordingCache;
+use crate::rules::Rules;
+use crate::{clipboard, postprocess, speech, validate};
+
+/// `talky transcribe <file>`: transcribes an audio file with a profile and prints the text
+pub fn transcribe(paths: &Paths, overrides: &Overrides, file: &Path, profile_name: &str) -> Result<()> {
+    let (config, api_key) = crate::load_config(paths, overrides)?;
+    let profile = config.profile(profile_name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown profile '{}'", profile_name))?;
+
+    let rules = Rules::load(&paths.rules_file)?;
+
+    let transcription = speech::transcribe_audio(file, &profile.api, &api_key, false)?;
+    println!("{}", postprocess::process(&transcription.text, &profile, &rules).trim_end());
+    Ok(())
+}
+
+/// `talky retranscribe`: transcribes a cached recording again and prints the text. It
+/// is only printed, the running Talky isn't involved.
+pub fn retranscribe(paths: &Paths, overrides: &Overrides, profile_name: &str, nth: usize) -> Result<()> {
+    let file = RecordingCache::new(paths.recent_dir()).nth_latest(nth)?;
+    transcribe(paths, overrides, &file, profile_name)
+}
+
+/// `talky ctl ...`: sends a command to the running Talky and prints its output
+#[cfg(unix)]
+pub fn ctl(paths: &Paths, command: CtlCommand) -> Result<()> {
+    let output = crate::ipc::send(&paths.socket_file(), &command.to_line())?;
+    print!("{}", output);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn ctl(_paths: &Paths, _command: CtlCommand) -> Result<()> {
+    anyhow::bail!("talky ctl is only supported on Unix")
+}
+
+// Copies a history entry through the running Talky when there is one. On X11 the
+// clipboard belongs to the process that set it, so text copied from here is gone as
+// soon as the command exits.
+fn copy_entry(paths: &Paths, history: &History, id: Option<u64>) -> Result<()> {
+    #[cfg(unix)]
+    if crate::ipc::is_running(&paths.socket_file()) {
+        let command = id.map_or("copy".to_string(), |id| format!("copy {}", id));
+        print!("{}", crate::ipc::send(&paths.socket_file(), &command)?);
+        return Ok(());
+    }
+
+    let entry = history.find(id)?;
+    clipboard::copy_to_clipboard(&entry.text)?;
+    println!("Copied transcription {} to the clipboard", entry.id);
+    #[cfg(target_os = "linux")]
+    println!("Talky isn't running, so on X11 the clipboard is cleared when this command exits");
+    Ok(())
+}
+
+/// `talky history ...`: lists, searches, copies, exports and prunes past transcriptions
+pub fn history(paths: &Paths, command: HistoryCommand) -> Result<()> {
+    let history = History::new(paths.history_file());
+
+    match command {
+        HistoryCommand::List { limit, profile } => {
+            let entries: Vec<Entry> = history.entries()?
+                .into_iter()
+                .filter(|e| profile.as_ref().is_none_or(|p| *p == e.profile))
+                .collect();
+            print_entries(&entries, limit);
+        },
+        HistoryCommand::Search { query, limit } => {
+            print_entries(&history.search(&query.join(" "))?, limit);
+        },
+        HistoryCommand::Copy { id } => copy_entry(paths, &history, id)?,
+        HistoryCommand::Export { format, output } => {
+            let entries = history.entries()?;
+            match output {
+                Some(path) => {
+                    let mut file = File::create(&path)
+                        .context(format!("Failed to create {}", path.display()))?;
+                    history::export(&entries, format, &mut file)?;
+                },
+                None => history::export(&entries, format, &mut std::io::stdout().lock())?,
+            }
+        },
+        HistoryCommand::Prune { older_than } => {
+            let removed = history.prune(history::parse_age(&older_than)?)?;
+            println!("Removed {} transcription(s)", removed);
+        },
+    }
+
+    Ok(())
+}
+
+// Prints the last `limit` entries, oldest first so the latest is at the bottom
+fn print_entries(entries: &[Entry], limit: usize) {
+    for entry in &entries[entries.len().saturating_sub(limit)..] {
+        println!("{:>5}  {}  {:<10} {}", entry.id, entry.time.format("%Y-%m-%d %H:%M"), entry.profile, entry.text);
+    }
+}
+
+/// `talky devices`: lists audio input devices, marking the default one
+pub fn devices() -> Result<()> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+
+    for device in host.input_devices()? {
+        let name = device.name()?;
+        let marker = if Some(&name) == default_name.as_ref() { "*" } else { " " };
+        match device.default_input_config() {
+            Ok(config) => println!("{} {} ({} channels, {} Hz)", marker, name, config.channels(), config.sample_rate().0),
+            Err(_) => println!("{} {}", marker, name),
+        }
+    }
+
+    Ok(())
+}
+
+/// `talky config init`: writes the default config file
+pub fn config_init(paths: &Paths, force: bool) -> Result<()> {
+    let config_path = &paths.config_file;
+    if config_path.exists() && !force {
+        return Err(anyhow::anyhow!("{} already exists, use --force to overwrite it", config_path.display()));
+    }
+
+    Config::write_default(config_path)?;
+    println!("Wrote default config to {}", config_path.display());
+    Ok(())
+}
+
+/// `talky config check`: validates the config file and prints every problem found.
+/// Exits with a non-zero status if there are any errors.
+pub fn config_check(paths: &Paths, overrides: &Overrides) -> Result<()> {
+    let config_path = &paths.config_file;
+    if !config_path.exists() {
+        return Err(anyhow::anyhow!("Config file not found: {}", config_path.display()));
+    }
+
+    let report = validate::check_file(config_path, overrides)?;
+    if !print_report(&report) {
+        std::process::exit(1);
+    }
+
+    if paths.rules_file.exists() {
+        let rules = match Rules::load(&paths.rules_file) {
+            Ok(rules) => rules,
+            Err(e) => {
+                println!("{:#}", e);
+                std::process::exit(1);
+            },
+        };
+        let config = Config::load(config_path, overrides)?;
+        let report = validate::check_rules(&paths.rules_file, &rules, &config)?;
+        if !print_report(&report) {
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+// Prints the diagnostics and a summary line, and returns false if there were errors
+fn print_report(report: &validate::Report) -> bool {
+    for diagnostic in &report.diagnostics {
+        println!("{}", report.format(diagnostic));
+    }
+
+    let warnings = report.diagnostics.len() - report.error_count();
+    if report.has_errors() {
+        println!("{}: {} error(s)", report.path.display(), report.error_count());
+    } else if warnings > 0 {
+        println!("{}: {} warning(s)", report.path.display(), warnings);
+    } else {
+        println!("{}: OK", report.path.display());
+    }
+    !report.has_errors()
+}
+
+/// `talky config show`: prints the effective config with overrides applied
+pub fn config_show(paths: &Paths, overrides: &Overrides) -> Result<()> {
+    let contents = std::fs::read_to_string(&paths.config_file)
+        .map_err(|e| anyhow::anyhow!("Failed to read config file {}: {}", paths.config_file.display(), e))?;
+    let config = Config::parse_with_overrides(&contents, overrides)?;
+
+    println!("# Config file: {}", paths.config_file.display());
+    println!("# API key file: {}", paths.api_key_file.display());
+    println!("# Rules file: {}", paths.rules_file.display());
+    print!("{}", serde_yaml::to_string(&config)?);
+    Ok(())
+}
+
+/// `talky doctor`: checks the config, API key, audio input, hotkey and API server
+/// and prints the result of each check
+pub fn doctor(paths: &Paths, overrides: &Overrides) -> Result<()> {
+    let mut failures = 0;
+    let mut report = |name: &str, result: Result<String>| match result {
+        Ok(detail) => println!("[ ok ] {}: {}", name, detail),
+        Err(e) => {
+            failures += 1;
+            println!("[FAIL] {}: {:#}", name, e);
+        },
+    };
+
+    let config = if paths.config_file.exists() {
+        validate::check_file(&paths.config_file, overrides)
+            .and_then(|r| match r.has_errors() {
+                true => Err(anyhow::anyhow!("{} error(s), run `talky config check`", r.error_count())),
+                false => Ok(()),
+            })
+            .and_then(|_| {
+                let contents = std::fs::read_to_string(&paths.config_file)?;
+                Config::parse_with_overrides(&contents, overrides)
+            })
+    } else {
+        Err(anyhow::anyhow!("{} not found, run `talky config init`", paths.config_file.display()))
+    };
+    let config = match config {
+        Ok(config) => {
+            report("config", Ok(paths.config_file.display().to_string()));
+            Some(config)
+        },
+        Err(e) => {
+            report("config", Err(e));
+            None
+        },
+    };
+
+    report("api key", check_api_key(&paths.api_key_file, config.as_ref()));
+    report("audio input", check_audio_input());
+
+    if let Some(config) = &config {
+        match config.listener.backend {
+            HotkeyBackend::GlobalHotkey => {
+                for binding in config.hotkey_bindings() {
+                    report(&format!("hotkey ({})", binding.key), check_hotkey(&binding.hotkey));
+                }
+            },
+            HotkeyBackend::Evdev => {
+                report("keyboards", check_keyboards(&config.listener.devices));
+                for binding in config.hotkey_bindings() {
+                    report(&format!("hotkey ({})", binding.key), check_evdev_hotkey(&binding.hotkey));
+                }
+            },
+        }
+        report("api server", check_api_server(&config.api.url));
+    }
+
+    if failures > 0 {
+        println!("{} check(s) failed", failures);
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn check_api_key(path: &Path, config: Option<&Config>) -> Result<String> {
+    if !path.exists() {
+        return Err(anyhow::anyhow!("{} not found", path.display()));
+    }
+
+    let api_key = ApiKeyConfig::load(path)?;
+    let is_local = config.is_some_and(|c| !c.api.url.contains("api.openai.com"));
+    if api_key.key == ApiKeyConfig::default().key && !is_local {
+        return Err(anyhow::anyhow!("{} still has the placeholder key", path.display()));
+    }
+
+    Ok(path.display().to_string())
+}
+
+fn check_audio_input() -> Result<String> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| anyhow::anyhow!("no default input device"))?;
+    let config = device.default_input_config()?;
+    Ok(format!("{} ({} Hz)", device.name()?, config.sample_rate().0))
+}
+
+// Registering and unregistering the hotkey shows whether another app (or another
+// copy of Talky) already has it
+fn check_hotkey(hotkey_string: &str) -> Result<String> {
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("DISPLAY").is_none() {
+        return Err(anyhow::anyhow!("no X11 display, global hotkeys need X11"));
+    }
+
+    let hotkey = HotKey::from_str(hotkey_string)?;
+    let manager = GlobalHotKeyManager::new()?;
+    manager.register(hotkey)?;
+    manager.unregister(hotkey)?;
+    Ok(hotkey_string.to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn check_keyboards(devices: &[PathBuf]) -> Result<String> {
+    let keyboards = crate::evdev_keys::open_keyboards(devices)?;
+    let paths: Vec<String> = keyboards.iter().map(|(path, _)| path.display().to_string()).collect();
+    Ok(paths.join(", "))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_keyboards(_devices: &[PathBuf]) -> Result<String> {
+    anyhow::bail!("the evdev backend only works on Linux")
+}
+
+#[cfg(target_os = "linux")]
+fn check_evdev_hotkey(hotkey_string: &str) -> Result<String> {
+    let hotkey = HotKey::from_str(hotkey_string)?;
+    if !crate::evdev_keys::can_press(&hotkey) {
+        anyhow::bail!("the evdev backend doesn't know the key in {}", hotkey_string);
+    }
+    Ok(hotkey_string.to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn check_evdev_hotkey(_hotkey_string: &str) -> Result<String> {
+    anyhow::bail!("the evdev backend only works on Linux")
+}
+
+// Any HTTP response means the server is reachable, even an error status
+fn check_api_server(url: &str) -> Result<String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()?;
+    let response = client.get(url).send()?;
+    Ok(format!("{} (HTTP {})", url, response.status().as_u16()))
+}
@@ -1,34 +1,953 @@
 use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use log::*;
 
+/// Config and API key together, as shared between threads
+pub type AppConfig = (Config, ApiKeyConfig);
+
+/// The live config. Threads take a snapshot with `load_full()` when they need it, and a
+/// reload swaps in the new config without affecting snapshots that are in use.
+pub type SharedConfig = Arc<ArcSwap<AppConfig>>;
+
+/// Name of the profile made from the top level `api`, `postprocess` and `output` sections
+pub const DEFAULT_PROFILE: &str = "default";
+
 /// Application configuration loaded from config.yaml
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     /// API configuration
     pub api: ApiConfig,
+
+    /// Cleanup applied to transcriptions before output
+    #[serde(default)]
+    pub postprocess: PostProcessConfig,
+
+    /// Where transcriptions go
+    #[serde(default)]
+    pub output: OutputConfig,
+
+    /// How recordings are queued for transcription
+    #[serde(default)]
+    pub transcription: TranscriptionConfig,
+
+    /// How the clipboard is handled when pasting
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+
+    /// How text is typed in the "type" output mode
+    #[serde(default)]
+    pub typing: TypingConfig,
+
+    /// How to paste into particular apps
+    #[serde(default)]
+    pub paste: PasteConfig,
+
+    /// Taking back the last transcription
+    #[serde(default)]
+    pub undo: UndoConfig,
+
+    /// Keeping recordings after they are transcribed
+    #[serde(default)]
+    pub recordings: RecordingsConfig,
+
+    /// Transcribing the last recording again with another profile
+    #[serde(default)]
+    pub retranscribe: RetranscribeConfig,
+
+    /// Keeping a record of every transcription
+    #[serde(default)]
+    pub history: HistoryConfig,
+
+    /// How hotkeys are read
+    #[serde(default)]
+    pub listener: ListenerConfig,
+
+    /// Desktop notifications
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    /// Sounds played on recording and transcription events
+    #[serde(default)]
+    pub cues: CuesConfig,
+
+    /// More hotkeys, from the key combination to what it does
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hotkeys: BTreeMap<String, HotkeyActions>,
+
+    /// Timing of hotkey taps, double taps and long presses
+    #[serde(default)]
+    pub gestures: GesturesConfig,
+
+    /// Hands-free recording, which is split into a transcription wherever you pause
+    #[serde(default)]
+    pub hands_free: HandsFreeConfig,
+
+    /// Named profiles. Each one overrides some of the settings above and can have its
+    /// own hotkey.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, ProfileConfig>,
 }
 
 /// API-related configuration
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ApiConfig {
+    /// Which kind of server the URL points at
+    #[serde(default)]
+    pub backend: Backend,
+
     /// URL for the speech-to-text API
     pub url: String,
 
     // Hotkey to trigger recording
     pub hotkey: String,
+    /// Hotkey that throws away the current recording. Only registered while recording.
+    /// Empty disables it.
+    #[serde(default = "default_cancel_hotkey")]
+    pub cancel_hotkey: String,
 
 
     // API parameters
     pub model: String,
     pub prompt: String,
+    /// Language of the speech as an ISO-639-1 code such as "en". Empty means auto-detect.
+    #[serde(default)]
+    pub language: String,
     pub temperature: f32,
     pub temperature_inc: f32,
 }
 
+fn default_cancel_hotkey() -> String {
+    "escape".to_string()
+}
+
+/// Speech-to-text server types
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// OpenAI's /v1/audio/transcriptions API, or a server compatible with it
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// whisper.cpp's server (also used by whisperfile), which ignores the model setting
+    #[serde(rename = "whisper_cpp")]
+    WhisperCpp,
+}
+
+/// Cleanup applied to the transcribed text
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PostProcessConfig {
+    /// Add a space after each transcription so consecutive ones don't run together
+    #[serde(default = "default_true")]
+    pub trailing_space: bool,
+    /// Turn spoken commands such as "new line" and "comma" into formatting
+    #[serde(default)]
+    pub spoken_commands: bool,
+    /// Use the built-in English commands as well as `commands`
+    #[serde(default = "default_true")]
+    pub builtin_commands: bool,
+    /// More spoken commands, from the phrase to the command, e.g. "punto": period
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub commands: BTreeMap<String, SpokenCommand>,
+    /// Saying this before a command writes the command's words instead. Empty disables it.
+    #[serde(default = "default_escape_phrase")]
+    pub escape_phrase: String,
+}
+
+fn default_escape_phrase() -> String {
+    "literal".to_string()
+}
+
+impl Default for PostProcessConfig {
+    fn default() -> Self {
+        Self {
+            trailing_space: true,
+            spoken_commands: false,
+            builtin_commands: true,
+            commands: BTreeMap::new(),
+            escape_phrase: default_escape_phrase(),
+        }
+    }
+}
+
+/// Formatting a spoken command stands for
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SpokenCommand {
+    Period,
+    Comma,
+    QuestionMark,
+    ExclamationMark,
+    Colon,
+    Semicolon,
+    NewLine,
+    NewParagraph,
+    OpenQuote,
+    CloseQuote,
+    OpenParen,
+    CloseParen,
+    /// Start a line with "- "
+    BulletPoint,
+    /// Write the following words in capitals, until `end_caps`
+    AllCaps,
+    EndCaps,
+    /// Join the words either side without a space
+    NoSpace,
+}
+
+/// Output settings
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct OutputConfig {
+    #[serde(default)]
+    pub mode: OutputMode,
+    /// Where each transcription is sent. When empty, only `mode` is used.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sinks: Vec<SinkConfig>,
+}
+
+impl OutputConfig {
+    /// The sinks to send transcriptions to, with `mode` standing in when none are listed
+    pub fn sinks(&self) -> Vec<SinkConfig> {
+        if !self.sinks.is_empty() {
+            return self.sinks.clone();
+        }
+
+        vec![match self.mode {
+            OutputMode::Paste => SinkConfig::Paste,
+            OutputMode::Clipboard => SinkConfig::Clipboard,
+            OutputMode::Type => SinkConfig::Type,
+        }]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    /// Copy to the clipboard and paste into the focused app
+    #[default]
+    Paste,
+    /// Only copy to the clipboard
+    Clipboard,
+    /// Type the text with synthetic key presses
+    Type,
+}
+
+/// Transcription queue settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TranscriptionConfig {
+    /// How many recordings are transcribed at the same time. Only read at startup.
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// Results are output in recording order. One that isn't ready this long after its
+    /// recording stopped is skipped, and goes to `late_sinks` when it arrives.
+    #[serde(default = "default_late_after_ms")]
+    pub late_after_ms: u64,
+    /// Where late results go instead of the profile's sinks
+    #[serde(default = "default_late_sinks")]
+    pub late_sinks: Vec<SinkConfig>,
+}
+
+impl Default for TranscriptionConfig {
+    fn default() -> Self {
+        Self {
+            workers: default_workers(),
+            late_after_ms: default_late_after_ms(),
+            late_sinks: default_late_sinks(),
+        }
+    }
+}
+
+fn default_workers() -> usize {
+    2
+}
+
+fn default_late_after_ms() -> u64 {
+    30_000
+}
+
+fn default_late_sinks() -> Vec<SinkConfig> {
+    vec![SinkConfig::Clipboard]
+}
+
+/// A place transcriptions are sent to, written in config as a map with a `type` key
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    /// Paste into the focused app, see the `paste` section
+    Paste,
+    /// Type into the focused app
+    Type,
+    /// Only copy to the clipboard
+    Clipboard,
+    /// Append a line to a file
+    File {
+        path: PathBuf,
+        /// Line written for each transcription. {text}, {profile} and {time} are replaced.
+        #[serde(default = "default_file_template")]
+        template: String,
+        /// chrono format used for {time}
+        #[serde(default = "default_time_format")]
+        time_format: String,
+    },
+    /// Print a JSON line to stdout
+    Stdout,
+    /// POST the transcription as JSON
+    Webhook {
+        url: String,
+        #[serde(default = "default_webhook_timeout_ms")]
+        timeout_ms: u64,
+    },
+    /// Write a JSON line to a Unix socket that something is listening on
+    Socket { path: PathBuf },
+    /// Write a JSON line to a named pipe. Skipped when nothing is reading from it.
+    Fifo { path: PathBuf },
+}
+
+fn default_file_template() -> String {
+    "{time} {text}".to_string()
+}
+
+fn default_time_format() -> String {
+    "%Y-%m-%d %H:%M:%S".to_string()
+}
+
+fn default_webhook_timeout_ms() -> u64 {
+    5000
+}
+
+/// Clipboard handling when pasting
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClipboardConfig {
+    /// How long to wait before pressing the paste keys, so the hotkey has been released
+    #[serde(default = "default_paste_delay_ms")]
+    pub paste_delay_ms: u64,
+    /// Put back what was on the clipboard before the transcription was pasted
+    #[serde(default = "default_true")]
+    pub restore: bool,
+    /// How long to wait after pasting before restoring, so the app has read the clipboard
+    #[serde(default = "default_restore_delay_ms")]
+    pub restore_delay_ms: u64,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            paste_delay_ms: default_paste_delay_ms(),
+            restore: true,
+            restore_delay_ms: default_restore_delay_ms(),
+        }
+    }
+}
+
+fn default_paste_delay_ms() -> u64 {
+    500
+}
+
+fn default_restore_delay_ms() -> u64 {
+    300
+}
+
+/// Settings for typing text with synthetic key presses
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TypingConfig {
+    /// How long to wait before typing, so the hotkey has been released
+    #[serde(default = "default_start_delay_ms")]
+    pub start_delay_ms: u64,
+    /// Delay between characters. 0 sends each chunk at once.
+    #[serde(default)]
+    pub char_delay_ms: u64,
+    /// Number of characters sent at a time
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+    /// Delay between chunks, which gives slow apps time to keep up
+    #[serde(default = "default_chunk_delay_ms")]
+    pub chunk_delay_ms: u64,
+}
+
+impl Default for TypingConfig {
+    fn default() -> Self {
+        Self {
+            start_delay_ms: default_start_delay_ms(),
+            char_delay_ms: 0,
+            chunk_size: default_chunk_size(),
+            chunk_delay_ms: default_chunk_delay_ms(),
+        }
+    }
+}
+
+fn default_start_delay_ms() -> u64 {
+    500
+}
+
+fn default_chunk_size() -> usize {
+    32
+}
+
+fn default_chunk_delay_ms() -> u64 {
+    10
+}
+
+/// Per-app paste settings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasteConfig {
+    /// Checked in order against the focused window, the first match wins
+    #[serde(default)]
+    pub rules: Vec<PasteRule>,
+    /// Use the built-in rules for common terminals after `rules`
+    #[serde(default = "default_true")]
+    pub builtin_rules: bool,
+}
+
+impl Default for PasteConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            builtin_rules: true,
+        }
+    }
+}
+
+/// Maps focused windows to a paste strategy. All conditions given must match.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasteRule {
+    /// WM_CLASS instance or class name, compared case-insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// Text contained in the window title, compared case-insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub paste: PasteStrategy,
+}
+
+/// How to get text into an app. Written in config as "type", "primary",
+/// "clipboard_only" or a key combination such as "ctrl+shift+v".
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(from = "String", into = "String")]
+pub enum PasteStrategy {
+    /// Copy to the clipboard and press these keys
+    Keys(String),
+    /// Type the text with key presses
+    Type,
+    /// Put the text in the X11 primary selection and press Shift+Insert
+    Primary,
+    /// Copy to the clipboard and let the user paste
+    ClipboardOnly,
+}
+
+impl From<String> for PasteStrategy {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "type" => PasteStrategy::Type,
+            "primary" => PasteStrategy::Primary,
+            "clipboard_only" => PasteStrategy::ClipboardOnly,
+            _ => PasteStrategy::Keys(value),
+        }
+    }
+}
+
+impl From<PasteStrategy> for String {
+    fn from(value: PasteStrategy) -> Self {
+        match value {
+            PasteStrategy::Keys(keys) => keys,
+            PasteStrategy::Type => "type".to_string(),
+            PasteStrategy::Primary => "primary".to_string(),
+            PasteStrategy::ClipboardOnly => "clipboard_only".to_string(),
+        }
+    }
+}
+
+/// Settings for removing the last pasted or typed transcription
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UndoConfig {
+    /// Hotkey that removes the last transcription. Empty disables it.
+    #[serde(default)]
+    pub hotkey: String,
+    /// How the text is removed
+    #[serde(default)]
+    pub method: UndoMethod,
+    /// Transcriptions that undo the previous one instead of being output
+    #[serde(default = "default_undo_phrases")]
+    pub phrases: Vec<String>,
+    /// Start a new recording with the same profile after undoing with the hotkey, so
+    /// the removed text can be dictated again
+    #[serde(default)]
+    pub redictate: bool,
+}
+
+impl Default for UndoConfig {
+    fn default() -> Self {
+        Self {
+            hotkey: String::new(),
+            method: UndoMethod::default(),
+            phrases: default_undo_phrases(),
+            redictate: false,
+        }
+    }
+}
+
+fn default_undo_phrases() -> Vec<String> {
+    vec!["scratch that".to_string()]
+}
+
+/// How to remove text from an app. Written in config as "backspace" or a key
+/// combination such as "ctrl+z" for the app's own undo.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(from = "String", into = "String")]
+pub enum UndoMethod {
+    /// Press backspace once per character
+    #[default]
+    Backspace,
+    /// Press these keys once
+    Keys(String),
+}
+
+impl From<String> for UndoMethod {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "backspace" => UndoMethod::Backspace,
+            _ => UndoMethod::Keys(value),
+        }
+    }
+}
+
+impl From<UndoMethod> for String {
+    fn from(value: UndoMethod) -> Self {
+        match value {
+            UndoMethod::Backspace => "backspace".to_string(),
+            UndoMethod::Keys(keys) => keys,
+        }
+    }
+}
+
+/// Settings for the cache of recent recordings
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordingsConfig {
+    /// How many transcribed recordings to keep. 0 deletes each one once transcribed.
+    #[serde(default = "default_keep_recordings")]
+    pub keep: usize,
+}
+
+impl Default for RecordingsConfig {
+    fn default() -> Self {
+        Self { keep: default_keep_recordings() }
+    }
+}
+
+fn default_keep_recordings() -> usize {
+    5
+}
+
+/// Settings for transcribing the last recording again
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RetranscribeConfig {
+    /// Hotkey that transcribes the last recording again. Empty disables it.
+    #[serde(default)]
+    pub hotkey: String,
+    /// Profile used for the hotkey, usually one with a slower, more accurate model
+    #[serde(default = "default_profile_name")]
+    pub profile: String,
+    /// Remove the text the recording was first transcribed to before outputting the new one
+    #[serde(default = "default_true")]
+    pub replace: bool,
+}
+
+impl Default for RetranscribeConfig {
+    fn default() -> Self {
+        Self {
+            hotkey: String::new(),
+            profile: default_profile_name(),
+            replace: true,
+        }
+    }
+}
+
+fn default_profile_name() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+/// Settings for the transcription history
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryConfig {
+    /// Record every transcription in the history file
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Store the path of the cached recording with each entry
+    #[serde(default = "default_true")]
+    pub audio_path: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { enabled: true, audio_path: true }
+    }
+}
+
+/// Ways of reading hotkeys
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyBackend {
+    /// The global-hotkey crate: X11, Windows and Mac. Needs a display.
+    #[default]
+    GlobalHotkey,
+    /// Key events read straight from /dev/input on Linux. Works on Wayland and consoles.
+    Evdev,
+}
+
+/// How hotkeys are read
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ListenerConfig {
+    #[serde(default)]
+    pub backend: HotkeyBackend,
+    /// Keyboards for the evdev backend. Empty uses every keyboard.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<PathBuf>,
+}
+
+/// What a hotkey in the `hotkeys` section does. Written in config as "toggle",
+/// "hold" or "hands_free", optionally followed by a profile name, "cancel", "undo",
+/// "retranscribe", "cycle_profile", "paste_last" or "pause".
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum HotkeyAction {
+    /// Start or stop recording. Without a profile the selected one is used.
+    Toggle(Option<String>),
+    /// Record while the keys are held down
+    Hold(Option<String>),
+    /// Record until stopped, transcribing whenever the speaker pauses
+    HandsFree(Option<String>),
+    /// Throw away the current recording. Only registered while recording.
+    Cancel,
+    /// Remove the last transcription
+    Undo,
+    /// Transcribe the last recording again
+    Retranscribe,
+    /// Select the next profile, for recordings started without naming one
+    CycleProfile,
+    /// Paste the last transcription again
+    PasteLast,
+    /// Pause or resume the current recording
+    Pause,
+}
+
+impl TryFrom<String> for HotkeyAction {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let (action, profile) = match value.trim().split_once(' ') {
+            Some((action, profile)) => (action, Some(profile.trim().to_string())),
+            None => (value.trim(), None),
+        };
+        let action = match (action, profile) {
+            ("toggle", profile) => HotkeyAction::Toggle(profile),
+            ("hold", profile) => HotkeyAction::Hold(profile),
+            ("hands_free", profile) => HotkeyAction::HandsFree(profile),
+            ("cancel", None) => HotkeyAction::Cancel,
+            ("undo", None) => HotkeyAction::Undo,
+            ("retranscribe", None) => HotkeyAction::Retranscribe,
+            ("cycle_profile", None) => HotkeyAction::CycleProfile,
+            ("paste_last", None) => HotkeyAction::PasteLast,
+            ("pause", None) => HotkeyAction::Pause,
+            _ => anyhow::bail!(
+                "unknown hotkey action '{}', expected toggle, hold, hands_free, cancel, undo, retranscribe, cycle_profile, paste_last or pause",
+                value
+            ),
+        };
+        Ok(action)
+    }
+}
+
+impl From<HotkeyAction> for String {
+    fn from(value: HotkeyAction) -> Self {
+        value.to_string()
+    }
+}
+
+impl std::fmt::Display for HotkeyAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HotkeyAction::Toggle(None) => write!(f, "toggle"),
+            HotkeyAction::Toggle(Some(profile)) => write!(f, "toggle {}", profile),
+            HotkeyAction::Hold(None) => write!(f, "hold"),
+            HotkeyAction::Hold(Some(profile)) => write!(f, "hold {}", profile),
+            HotkeyAction::HandsFree(None) => write!(f, "hands_free"),
+            HotkeyAction::HandsFree(Some(profile)) => write!(f, "hands_free {}", profile),
+            HotkeyAction::Cancel => write!(f, "cancel"),
+            HotkeyAction::Undo => write!(f, "undo"),
+            HotkeyAction::Retranscribe => write!(f, "retranscribe"),
+            HotkeyAction::CycleProfile => write!(f, "cycle_profile"),
+            HotkeyAction::PasteLast => write!(f, "paste_last"),
+            HotkeyAction::Pause => write!(f, "pause"),
+        }
+    }
+}
+
+/// Actions for a hotkey in the `hotkeys` section: one action, or a map with an action
+/// for each gesture
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "Value", into = "Value")]
+pub enum HotkeyActions {
+    Action(HotkeyAction),
+    Gestures(GestureActions),
+}
+
+impl HotkeyActions {
+    /// Every action the hotkey can run
+    pub fn all(&self) -> Vec<&HotkeyAction> {
+        match self {
+            HotkeyActions::Action(action) => vec![action],
+            HotkeyActions::Gestures(gestures) => {
+                [&gestures.tap, &gestures.double_tap, &gestures.long_press].into_iter().flatten().collect()
+            },
+        }
+    }
+}
+
+impl TryFrom<Value> for HotkeyActions {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::String(action) => Ok(HotkeyActions::Action(HotkeyAction::try_from(action)?)),
+            Value::Mapping(_) => Ok(HotkeyActions::Gestures(serde_yaml::from_value(value)?)),
+            _ => anyhow::bail!("expected an action, or tap, double_tap and long_press actions"),
+        }
+    }
+}
+
+impl From<HotkeyActions> for Value {
+    fn from(value: HotkeyActions) -> Self {
+        match value {
+            HotkeyActions::Action(action) => Value::String(action.to_string()),
+            HotkeyActions::Gestures(gestures) => serde_yaml::to_value(gestures).unwrap_or_default(),
+        }
+    }
+}
+
+impl std::fmt::Display for HotkeyActions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HotkeyActions::Action(action) => write!(f, "{}", action),
+            HotkeyActions::Gestures(gestures) => {
+                let names = [("tap", &gestures.tap), ("double tap", &gestures.double_tap), ("long press", &gestures.long_press)];
+                let described: Vec<String> = names.into_iter()
+                    .filter_map(|(name, action)| action.as_ref().map(|a| format!("{} ({})", a, name)))
+                    .collect();
+                write!(f, "{}", described.join(", "))
+            },
+        }
+    }
+}
+
+/// An action for each way of pressing a hotkey. A tap waits to see whether it becomes
+/// a double tap or long press, but only if one of those is set.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct GestureActions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap: Option<HotkeyAction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub double_tap: Option<HotkeyAction>,
+    /// Fires while the keys are still held down, so "hold" records until they're let go
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub long_press: Option<HotkeyAction>,
+}
+
+/// A hotkey and its actions, from any of the settings that take a hotkey
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotkeyBinding {
+    /// Where it was set, e.g. "undo.hotkey", for messages
+    pub key: String,
+    pub hotkey: String,
+    pub actions: HotkeyActions,
+}
+
+/// How hotkey gestures are told apart
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GesturesConfig {
+    /// A second press within this time of letting go makes a double tap
+    #[serde(default = "default_double_tap_ms")]
+    pub double_tap_ms: u64,
+    /// Holding the keys down this long makes a long press
+    #[serde(default = "default_long_press_ms")]
+    pub long_press_ms: u64,
+}
+
+fn default_double_tap_ms() -> u64 {
+    300
+}
+
+fn default_long_press_ms() -> u64 {
+    400
+}
+
+impl Default for GesturesConfig {
+    fn default() -> Self {
+        Self {
+            double_tap_ms: default_double_tap_ms(),
+            long_press_ms: default_long_press_ms(),
+        }
+    }
+}
+
+/// Settings for hands-free recording
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HandsFreeConfig {
+    /// Input level, 0.0 to 1.0, above which the microphone is hearing speech
+    #[serde(default = "default_voice_threshold")]
+    pub threshold: f32,
+    /// Quiet after speech for this long ends a transcription
+    #[serde(default = "default_silence_ms")]
+    pub silence_ms: u64,
+}
+
+fn default_voice_threshold() -> f32 {
+    0.08
+}
+
+fn default_silence_ms() -> u64 {
+    1200
+}
+
+impl Default for HandsFreeConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_voice_threshold(),
+            silence_ms: default_silence_ms(),
+        }
+    }
+}
+
+/// Which desktop notifications to show
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationsConfig {
+    /// Recording started and stopped
+    #[serde(default)]
+    pub recording: bool,
+    /// A recording was cancelled and thrown away
+    #[serde(default = "default_true")]
+    pub cancelled: bool,
+    /// Transcription, output or recording failed, with the reason
+    #[serde(default = "default_true")]
+    pub failed: bool,
+    /// The API couldn't be reached. Says whether the recording was kept for later.
+    #[serde(default = "default_true")]
+    pub unreachable: bool,
+    /// The model wasn't sure about a transcription
+    #[serde(default)]
+    pub low_confidence: bool,
+    /// Below this confidence, 0.0 to 1.0, a transcription counts as low confidence
+    #[serde(default = "default_low_confidence_threshold")]
+    pub low_confidence_threshold: f32,
+}
+
+fn default_low_confidence_threshold() -> f32 {
+    0.5
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            recording: false,
+            cancelled: true,
+            failed: true,
+            unreachable: true,
+            low_confidence: false,
+            low_confidence_threshold: default_low_confidence_threshold(),
+        }
+    }
+}
+
+/// Sound cues. Each cue is a built-in tone unless a WAV file is given for it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CuesConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// 0.0 to 1.0
+    #[serde(default = "default_cue_volume")]
+    pub volume: f32,
+    /// Recording started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<PathBuf>,
+    /// Recording stopped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<PathBuf>,
+    /// Recording cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancel: Option<PathBuf>,
+    /// Transcription output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub success: Option<PathBuf>,
+    /// Transcription failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<PathBuf>,
+}
+
+fn default_cue_volume() -> f32 {
+    0.5
+}
+
+impl Default for CuesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            volume: default_cue_volume(),
+            start: None,
+            stop: None,
+            cancel: None,
+            success: None,
+            error: None,
+        }
+    }
+}
+
+/// A named profile. Settings that are left out fall back to the top level ones.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ProfileConfig {
+    /// Hotkey that starts a recording with this profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotkey: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<Backend>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_inc: Option<f32>,
+    /// Post-processing settings laid over the top level `postprocess` section, so
+    /// settings the profile leaves out keep their top level values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub postprocess: Option<Value>,
+    /// Output settings laid over the top level `output` section
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<Value>,
+}
+
+/// A profile with all settings filled in
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub api: ApiConfig,
+    pub postprocess: PostProcessConfig,
+    pub output: OutputConfig,
+}
+
+fn default_true() -> bool {
+    true
+}
+
 /// API Key configuration loaded from apikey.yaml
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ApiKeyConfig {
@@ -44,52 +963,528 @@ impl Default for ApiKeyConfig {
     }
 }
 
-impl Config {
-    /// Load configuration from the specified path
-    pub fn load(path: &Path) -> Result<Self> {
-        // Check if the file exists
-        if path.exists() {
-            // Open and read the file
-            let mut file = File::open(path)
-                .context(format!("Failed to open config file: {}", path.display()))?;
-            
-            let mut contents = String::new();
-            file.read_to_string(&mut contents)
-                .context("Failed to read config file")?;
-            
-            // Parse YAML
-            let config: Config = serde_yaml::from_str(&contents)
-                .context("Failed to parse config file")?;
-
-            
-            Ok(config)
-        } else {
-            info!("Config file not found, creating default config at {}", path.display());
-
-            let default_config = r#"
+/// Default contents written to config.yaml when it doesn't exist
+pub const DEFAULT_CONFIG: &str = r#"
 api:
   # URL for the speech-to-text API
-  # To use a local model, set this to the address of the model such as "localhost:8080"
+  # To use a local model, set this to the address of the model such as "http://localhost:8080"
   url: "https://api.openai.com/v1/audio/transcriptions"
 
   hotkey: "ctrl+shift+space"
+  # Throws away the recording in progress without transcribing it. Only taken while
+  # recording, so it doesn't stop other apps getting the key. Empty disables it.
+  cancel_hotkey: "escape"
 
   # To understand these, see https://platform.openai.com/docs/api-reference/audio/createTranscription
   model: "whisper-1"
   prompt: ""
   temperature: 0.0
-  temperature_inc: 0.2"#;
+  temperature_inc: 0.2
 
-            //write the string directly to the config file path
-            std::fs::write(path, default_config)
-                .context(format!("Failed to write default config file: {}", path.display()))?;
+  # "openai" for OpenAI compatible servers, "whisper_cpp" for whisper.cpp's server or a
+  # whisperfile, which use the model they were started with
+  backend: openai
 
-            let config: Config = serde_yaml::from_str(default_config)
-                .context("Failed to parse default config file")?;
-            
-            Ok(config)
+  # Language of the speech, e.g. "en". Leave empty to detect it automatically
+  language: ""
+
+postprocess:
+  # Add a space after each transcription
+  trailing_space: true
+  # Turn spoken commands into formatting: "period", "comma", "question mark",
+  # "exclamation mark", "colon", "semicolon", "new line", "new paragraph",
+  # "open quote", "close quote", "open paren", "close paren", "bullet point",
+  # "all caps" ... "end caps" and "no space". They match anywhere in what you
+  # say, so "a period of time" gets a full stop too; say the escape phrase first
+  # or turn off builtin_commands and pick your own in commands.
+  spoken_commands: false
+  # Saying this first writes a command's words instead, e.g. "literal comma"
+  escape_phrase: "literal"
+  # Commands in other languages, from the phrase to one of the commands above written
+  # like new_line. Set builtin_commands to false to turn off the English ones.
+  builtin_commands: true
+  # commands:
+  #   "punto": period
+  #   "nueva línea": new_line
+
+output:
+  # "paste" pastes into the focused app, "clipboard" only copies to the clipboard,
+  # "type" types the text with key presses for apps that don't accept paste
+  mode: paste
+  # To send transcriptions to several places, list sinks instead. Each one works
+  # independently, so one failing doesn't stop the others.
+  # sinks:
+  #   - type: paste
+  #   - type: file
+  #     path: "/home/me/dictation.log"
+  #     template: "{time} [{profile}] {text}"
+  #   - type: stdout
+  #   - type: webhook
+  #     url: "http://localhost:5000/transcripts"
+  #   - type: socket
+  #     path: "/tmp/talky.sock"
+  #   - type: fifo
+  #     path: "/tmp/talky.fifo"
+
+transcription:
+  # How many recordings are transcribed at the same time
+  workers: 2
+  # Transcriptions are output in the order they were recorded. One that takes longer
+  # than this after its recording stopped is skipped, and goes to late_sinks instead,
+  # so it isn't typed wherever the cursor has moved to since
+  late_after_ms: 30000
+  late_sinks:
+    - type: clipboard
+
+clipboard:
+  # How long to wait before pasting, so the hotkey has been released
+  paste_delay_ms: 500
+  # Put back what was on the clipboard before pasting
+  restore: true
+  # How long to wait after pasting before restoring it
+  restore_delay_ms: 300
+
+# Used by the "type" output mode, and when the clipboard can't be used for pasting
+typing:
+  # How long to wait before typing, so the hotkey has been released
+  start_delay_ms: 500
+  # Delay between characters, 0 sends a whole chunk at once
+  char_delay_ms: 0
+  # Characters sent at a time, and the delay between chunks
+  chunk_size: 32
+  chunk_delay_ms: 10
+
+# In paste mode, how to paste into particular apps. Rules are checked in order against
+# the focused window (X11 only). "paste" is a key combination, "type", "primary" (X11
+# primary selection + Shift+Insert) or "clipboard_only".
+paste:
+  # Built-in rules for common terminals, which need ctrl+shift+v or the primary selection
+  builtin_rules: true
+  rules: []
+  # rules:
+  #   - class: "emacs"
+  #     paste: "ctrl+y"
+  #   - title: "password"
+  #     paste: type
+
+# Taking back the last pasted or typed transcription
+undo:
+  # Hotkey that removes it, e.g. "ctrl+shift+backspace". Empty disables it.
+  hotkey: ""
+  # "backspace" deletes it a character at a time, or a key combination such as
+  # "ctrl+z" uses the app's undo
+  method: backspace
+  # Saying one of these on its own removes the previous transcription
+  phrases:
+    - "scratch that"
+  # Start recording again after the hotkey removes the text
+  redictate: false
+
+recordings:
+  # How many recordings to keep after transcribing, for re-transcribing them.
+  # 0 deletes each one as soon as it has been transcribed
+  keep: 5
+
+# Transcribing the last recording again, e.g. with a more accurate model
+retranscribe:
+  # Hotkey for it, e.g. "ctrl+shift+r". Empty disables it.
+  hotkey: ""
+  # Profile to transcribe with
+  profile: default
+  # Remove the text from the first transcription and put the new text in its place
+  replace: true
+
+# Every transcription is recorded in history.jsonl in the data directory, see
+# `talky history --help`
+history:
+  enabled: true
+  # Store where the recording is cached with each entry
+  audio_path: true
+
+# How hotkeys are read. "global_hotkey" works on X11, Windows and Mac. "evdev" reads
+# keyboards from /dev/input on Linux, so it also works on Wayland and without a display;
+# it needs read access to /dev/input, usually through the "input" group.
+# Changing this needs a restart.
+listener:
+  backend: global_hotkey
+  # Keyboards for evdev, e.g. /dev/input/by-id/usb-...-event-kbd. Empty uses all of them.
+  # devices: []
 
+# Desktop notifications (Linux), each kind can be turned on or off
+notifications:
+  # Recording started and stopped
+  recording: false
+  # A recording was cancelled and thrown away
+  cancelled: true
+  # Transcription, output or recording failed, with the reason
+  failed: true
+  # The API couldn't be reached, and whether the recording was kept for
+  # `talky retranscribe`
+  unreachable: true
+  # The model wasn't sure about a transcription (whisper models only)
+  low_confidence: false
+  low_confidence_threshold: 0.5
+
+# Sounds when recording starts, stops or is cancelled, and when a transcription is
+# output or fails. The microphone is muted while the start cue plays.
+cues:
+  enabled: false
+  volume: 0.5
+  # WAV files to play instead of the built-in tones
+  # start: "/home/me/sounds/start.wav"
+  # stop, cancel, success, error
+
+# More hotkeys, each bound to an action: "toggle", "hold" (record while held down) or
+# "hands_free" (see below), optionally followed by a profile name to record with
+# instead of the selected one, "cancel", "undo", "retranscribe", "cycle_profile"
+# (select the next profile), "paste_last" (paste the last transcription again) or
+# "pause" (pause or resume the recording). Instead of one action a hotkey can have
+# one for each of tap, double_tap and long_press. A key combination can only be used once.
+# hotkeys:
+#   "ctrl+alt+space":
+#     tap: toggle
+#     double_tap: hands_free
+#     long_press: hold
+#   "ctrl+alt+p": paste_last
+#   "ctrl+alt+tab": cycle_profile
+#   "scrolllock": pause
+
+# How quickly a second tap must follow, and how long a long press is
+gestures:
+  double_tap_ms: 300
+  long_press_ms: 400
+
+# Hands-free recording keeps listening until it's stopped, and transcribes what you
+# said each time you pause
+hands_free:
+  # Input level (0.0 to 1.0) that counts as speech. Raise it in a noisy room.
+  threshold: 0.08
+  # How long a pause ends a transcription
+  silence_ms: 1200
+
+# Profiles have their own hotkey and can override the api settings (backend, url,
+# model, prompt, language, temperature, temperature_inc) and the postprocess and
+# output sections. Anything else is shared with the top level. A recording started
+# with a profile's hotkey is transcribed and output with that profile.
+# profiles:
+#   docs:
+#     hotkey: "ctrl+shift+d"
+#     model: "gpt-4o-transcribe"
+#     prompt: "Technical documentation about Rust."
+#   chat:
+#     hotkey: "ctrl+shift+c"
+#     model: "gpt-4o-mini-transcribe"
+#     output:
+#       mode: clipboard"#;
+
+/// Prefix of environment variables that override config values, e.g. TALKY_API_URL
+const ENV_PREFIX: &str = "TALKY_";
+
+/// Config values set from the command line (`--set api.model=whisper-1`) or from
+/// TALKY_* environment variables. These are applied on top of config.yaml, with the
+/// command line taking precedence over the environment.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// Environment variables with the prefix removed, e.g. ("API_URL", "http://...")
+    env: Vec<(String, String)>,
+    /// Dotted keys and values from the command line
+    args: Vec<(String, String)>,
+}
+
+impl Overrides {
+    /// Collects TALKY_* environment variables and parses `key=value` pairs from the command line
+    pub fn new(sets: &[String]) -> Result<Self> {
+        let env = std::env::vars()
+            .filter_map(|(name, value)| name.strip_prefix(ENV_PREFIX).map(|n| (n.to_string(), value)))
+            .collect();
+
+        let args = sets.iter()
+            .map(|set| {
+                set.split_once('=')
+                    .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid override '{}', expected key=value", set))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { env, args })
+    }
+
+    /// Applies the overrides to a parsed YAML tree and returns the dotted keys that were set
+    pub fn apply(&self, root: &mut Value) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+
+        for (name, value) in &self.env {
+            match env_key(root, name) {
+                Some(key) => {
+                    set_value(root, &key, value)?;
+                    keys.push(key);
+                },
+                None => debug!("Ignoring environment variable {}{}, it doesn't match a config key", ENV_PREFIX, name),
+            }
+        }
+
+        for (key, value) in &self.args {
+            set_value(root, key, value)?;
+            keys.push(key.clone());
+        }
+
+        Ok(keys)
+    }
+}
+
+/// Maps an environment variable name such as "API_TEMPERATURE_INC" onto a dotted key
+/// ("api.temperature_inc") by matching it against the keys present in the config tree.
+/// Returns None if the first segment doesn't match a top level key.
+fn env_key(root: &Value, name: &str) -> Option<String> {
+    let mut node = root;
+    let mut rest = name;
+    let mut segments: Vec<String> = Vec::new();
+
+    while !rest.is_empty() {
+        // Find the longest existing key that matches the start of the remaining name
+        let found = node.as_mapping().and_then(|mapping| {
+            mapping.iter()
+                .filter_map(|(k, v)| k.as_str().map(|k| (k, v)))
+                .filter(|(k, _)| {
+                    let upper = k.to_uppercase();
+                    rest == upper || rest.starts_with(&format!("{}_", upper))
+                })
+                .max_by_key(|(k, _)| k.len())
+        });
+
+        match found {
+            Some((key, child)) => {
+                segments.push(key.to_string());
+                rest = rest[key.len()..].trim_start_matches('_');
+                node = child;
+            },
+            // Keys missing from config.yaml (optional values) take the rest of the name
+            None if !segments.is_empty() => {
+                segments.push(rest.to_lowercase());
+                rest = "";
+            },
+            None => return None,
+        }
+    }
+
+    Some(segments.join("."))
+}
+
+/// Sets a dotted key in a YAML tree, creating intermediate mappings as needed.
+/// The value is parsed as YAML unless the key holds text, so numbers and booleans
+/// keep their types and text such as "Notes: x" or "yes" stays text.
+fn set_value(root: &mut Value, key: &str, value: &str) -> Result<()> {
+    let segments: Vec<&str> = key.split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(anyhow::anyhow!("Invalid config key '{}'", key));
+    }
+    let parsed = parse_value(value, current_value(root, &segments).as_ref());
+
+    let mut node = root;
+    for segment in segments {
+        if !node.is_mapping() {
+            *node = Value::Mapping(Mapping::new());
+        }
+        let Some(mapping) = node.as_mapping_mut() else {
+            unreachable!("node was just made a mapping");
+        };
+        node = mapping.entry(Value::String(segment.to_string())).or_insert(Value::Null);
+    }
+    *node = parsed;
+
+    Ok(())
+}
+
+/// The value a key has now, or its default if config.yaml leaves it out. Profile
+/// settings have the type of the top level setting they override.
+fn current_value(root: &Value, segments: &[&str]) -> Option<Value> {
+    let lookup = |tree: &Value, segments: &[&str]| {
+        segments.iter()
+            .try_fold(tree, |node, segment| node.get(*segment))
+            .filter(|value| !value.is_null())
+            .cloned()
+    };
+
+    let defaults = Config::parse(DEFAULT_CONFIG).ok().and_then(|config| serde_yaml::to_value(config).ok())?;
+    let top_level: Vec<&str> = match segments {
+        ["profiles", _, section @ ("postprocess" | "output"), rest @ ..] => [*section].into_iter().chain(rest.iter().copied()).collect(),
+        ["profiles", _, rest @ ..] => ["api"].into_iter().chain(rest.iter().copied()).collect(),
+        _ => segments.to_vec(),
+    };
+
+    lookup(root, segments).or_else(|| lookup(&defaults, &top_level))
+}
+
+// Parses an override value as the type of what it replaces. Where that isn't known,
+// only booleans, numbers and [..] or {..} are taken as YAML.
+fn parse_value(value: &str, current: Option<&Value>) -> Value {
+    let text = Value::String(value.to_string());
+    if value.is_empty() {
+        return text;
+    }
+
+    match current {
+        Some(Value::String(_)) => text,
+        Some(_) => serde_yaml::from_str(value).unwrap_or(text),
+        None => match serde_yaml::from_str::<Value>(value) {
+            Ok(parsed @ (Value::Bool(_) | Value::Number(_))) => parsed,
+            Ok(parsed @ (Value::Sequence(_) | Value::Mapping(_))) if value.trim_start().starts_with(['[', '{']) => parsed,
+            _ => text,
+        },
+    }
+}
+
+/// Lays the settings in `changes` over `base`. Mappings are merged key by key, other
+/// values replace what's in `base`.
+pub fn overlay<T: Serialize + DeserializeOwned>(base: &T, changes: Option<&Value>) -> Result<T> {
+    let mut value = serde_yaml::to_value(base)?;
+    if let Some(changes) = changes {
+        merge(&mut value, changes);
+    }
+    Ok(serde_yaml::from_value(value)?)
+}
+
+fn merge(base: &mut Value, changes: &Value) {
+    match (base, changes) {
+        (Value::Mapping(base), Value::Mapping(changes)) => {
+            for (key, change) in changes {
+                match base.get_mut(key) {
+                    Some(value) => merge(value, change),
+                    None => {
+                        base.insert(key.clone(), change.clone());
+                    },
+                }
+            }
+        },
+        (base, changes) => *base = changes.clone(),
+    }
+}
+
+impl Config {
+    /// Returns the named profile with unset values taken from the top level settings.
+    /// "default" is the top level settings themselves.
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        if name == DEFAULT_PROFILE {
+            return Some(Profile {
+                name: DEFAULT_PROFILE.to_string(),
+                api: self.api.clone(),
+                postprocess: self.postprocess.clone(),
+                output: self.output.clone(),
+            });
+        }
+
+        let profile = self.profiles.get(name)?;
+        let api = ApiConfig {
+            backend: profile.backend.unwrap_or(self.api.backend),
+            url: profile.url.clone().unwrap_or_else(|| self.api.url.clone()),
+            hotkey: profile.hotkey.clone().unwrap_or_default(),
+            cancel_hotkey: self.api.cancel_hotkey.clone(),
+            model: profile.model.clone().unwrap_or_else(|| self.api.model.clone()),
+            prompt: profile.prompt.clone().unwrap_or_else(|| self.api.prompt.clone()),
+            language: profile.language.clone().unwrap_or_else(|| self.api.language.clone()),
+            temperature: profile.temperature.unwrap_or(self.api.temperature),
+            temperature_inc: profile.temperature_inc.unwrap_or(self.api.temperature_inc),
+        };
+
+        // Validation reports sections that don't parse, so this only logs them
+        let postprocess = overlay(&self.postprocess, profile.postprocess.as_ref()).unwrap_or_else(|e| {
+            debug!("Ignoring profiles.{}.postprocess: {:#}", name, e);
+            self.postprocess.clone()
+        });
+        // A profile that picks a mode wants that mode, not the top level sinks
+        let mut base_output = self.output.clone();
+        if profile.output.as_ref().is_some_and(|o| o.get("mode").is_some() && o.get("sinks").is_none()) {
+            base_output.sinks.clear();
         }
+        let output = overlay(&base_output, profile.output.as_ref()).unwrap_or_else(|e| {
+            debug!("Ignoring profiles.{}.output: {:#}", name, e);
+            self.output.clone()
+        });
+
+        Some(Profile {
+            name: name.to_string(),
+            api,
+            postprocess,
+            output,
+        })
+    }
+
+    /// Like `profile`, but falls back to the default profile if the name is unknown,
+    /// for example because the profile was removed by a config reload
+    pub fn profile_or_default(&self, name: &str) -> Profile {
+        self.profile(name).unwrap_or_else(|| {
+            warn!("Profile '{}' not found, using the default profile", name);
+            self.profile(DEFAULT_PROFILE).expect("default profile always exists")
+        })
+    }
+
+    /// Every hotkey that is set: the profile hotkeys, the cancel, undo and re-transcribe
+    /// hotkeys, then the `hotkeys` section
+    pub fn hotkey_bindings(&self) -> Vec<HotkeyBinding> {
+        let mut settings = vec![(
+            "api.hotkey".to_string(),
+            &self.api.hotkey,
+            HotkeyActions::Action(HotkeyAction::Toggle(Some(DEFAULT_PROFILE.to_string()))),
+        )];
+        for (name, profile) in &self.profiles {
+            if let Some(hotkey) = &profile.hotkey {
+                settings.push((format!("profiles.{}.hotkey", name), hotkey, HotkeyActions::Action(HotkeyAction::Toggle(Some(name.clone())))));
+            }
+        }
+        let actions = [
+            ("api.cancel_hotkey", &self.api.cancel_hotkey, HotkeyAction::Cancel),
+            ("undo.hotkey", &self.undo.hotkey, HotkeyAction::Undo),
+            ("retranscribe.hotkey", &self.retranscribe.hotkey, HotkeyAction::Retranscribe),
+        ];
+        for (key, hotkey, action) in actions {
+            settings.push((key.to_string(), hotkey, HotkeyActions::Action(action)));
+        }
+        for (hotkey, actions) in &self.hotkeys {
+            settings.push((format!("hotkeys.{}", hotkey), hotkey, actions.clone()));
+        }
+
+        settings.into_iter()
+            .filter(|(_, hotkey, _)| !hotkey.trim().is_empty())
+            .map(|(key, hotkey, actions)| HotkeyBinding { key, hotkey: hotkey.clone(), actions })
+            .collect()
+    }
+
+    /// Parse configuration from YAML text
+    pub fn parse(contents: &str) -> std::result::Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(contents)
+    }
+
+    /// Parse configuration from YAML text and apply overrides on top of it
+    pub fn parse_with_overrides(contents: &str, overrides: &Overrides) -> Result<Self> {
+        let mut value: Value = serde_yaml::from_str(contents)?;
+        overrides.apply(&mut value)?;
+        Ok(serde_yaml::from_value(value)?)
+    }
+
+    /// Writes the default config file
+    pub fn write_default(path: &Path) -> Result<()> {
+        std::fs::write(path, DEFAULT_CONFIG)
+            .context(format!("Failed to write default config file: {}", path.display()))
+    }
+
+    /// Load configuration from the specified path, creating a default config if it doesn't exist
+    pub fn load(path: &Path, overrides: &Overrides) -> Result<Self> {
+        if !path.exists() {
+            info!("Config file not found, creating default config at {}", path.display());
+            Config::write_default(path)?;
+        }
+
+        // Open and read the file
+        let mut file = File::open(path)
+            .context(format!("Failed to open config file: {}", path.display()))?;
+        
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .context("Failed to read config file")?;
+        
+        // Parse YAML
+        Config::parse_with_overrides(&contents, overrides)
+            .context("Failed to parse config file")
     }
 }
 
@@ -147,3 +1542,104 @@ impl ApiKeyConfig {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_overrides(sets: &[&str]) -> Config {
+        let overrides = Overrides {
+            env: Vec::new(),
+            args: sets.iter().map(|set| {
+                let (key, value) = set.split_once('=').unwrap();
+                (key.to_string(), value.to_string())
+            }).collect(),
+        };
+        Config::parse_with_overrides(DEFAULT_CONFIG, &overrides).unwrap()
+    }
+
+    #[test]
+    fn string_overrides_stay_text() {
+        let config = with_overrides(&["api.prompt=Notes: x", "api.model=123", "api.language=yes"]);
+        assert_eq!(config.api.prompt, "Notes: x");
+        assert_eq!(config.api.model, "123");
+        assert_eq!(config.api.language, "yes");
+    }
+
+    #[test]
+    fn bool_and_number_overrides_are_parsed() {
+        let config = with_overrides(&["clipboard.restore=false", "api.temperature=0.4", "transcription.workers=3"]);
+        assert!(!config.clipboard.restore);
+        assert_eq!(config.api.temperature, 0.4);
+        assert_eq!(config.transcription.workers, 3);
+    }
+
+    #[test]
+    fn wrong_type_is_an_error() {
+        let overrides = Overrides { env: Vec::new(), args: vec![("clipboard.restore".into(), "yes".into())] };
+        assert!(Config::parse_with_overrides(DEFAULT_CONFIG, &overrides).is_err());
+    }
+
+    #[test]
+    fn nested_overrides_create_profiles() {
+        let config = with_overrides(&[
+            "profiles.notes.prompt=Notes: x",
+            "profiles.notes.model=123",
+            "profiles.notes.temperature=0.2",
+            "profiles.notes.postprocess.trailing_space=false",
+        ]);
+        let profile = config.profile("notes").unwrap();
+        assert_eq!(profile.api.prompt, "Notes: x");
+        assert_eq!(profile.api.model, "123");
+        assert_eq!(profile.api.temperature, 0.2);
+        assert!(!profile.postprocess.trailing_space);
+    }
+
+    #[test]
+    fn profile_sections_are_merged_over_the_top_level() {
+        let config = Config::parse("
+api:
+  url: http://localhost:8080
+  hotkey: ctrl+shift+space
+  model: whisper-1
+  prompt: ''
+  temperature: 0.0
+  temperature_inc: 0.2
+postprocess:
+  trailing_space: false
+  spoken_commands: true
+output:
+  sinks:
+    - type: stdout
+profiles:
+  notes:
+    postprocess:
+      escape_phrase: verbatim
+  chat:
+    output:
+      mode: clipboard
+").unwrap();
+        let notes = config.profile("notes").unwrap();
+        assert!(!notes.postprocess.trailing_space);
+        assert!(notes.postprocess.spoken_commands);
+        assert_eq!(notes.postprocess.escape_phrase, "verbatim");
+        assert_eq!(notes.output.sinks.len(), 1);
+
+        let chat = config.profile("chat").unwrap();
+        assert!(chat.postprocess.spoken_commands);
+        assert_eq!(chat.output.mode, OutputMode::Clipboard);
+        assert!(chat.output.sinks.is_empty());
+    }
+
+    #[test]
+    fn unknown_keys_only_parse_plain_values() {
+        let mut root = Value::Mapping(Mapping::new());
+        set_value(&mut root, "a.text", "Notes: x").unwrap();
+        set_value(&mut root, "a.flag", "true").unwrap();
+        set_value(&mut root, "a.list", "[1, 2]").unwrap();
+        assert_eq!(root["a"]["text"], Value::String("Notes: x".into()));
+        assert_eq!(root["a"]["flag"], Value::Bool(true));
+        assert!(root["a"]["list"].is_sequence());
+        assert!(set_value(&mut root, "a..b", "1").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::config::DEFAULT_PROFILE;
+
+/// Recording state shared between the hotkey listener and the recording thread.
+/// The listener changes it and the recording thread polls it.
+pub struct Control {
+    recording: AtomicBool,
+    /// Set when a recording is stopped by cancelling, until the recording thread sees it
+    cancelled: AtomicBool,
+    /// Set while recording hands-free, so the recording thread splits the recording
+    /// wherever the speaker pauses
+    hands_free: AtomicBool,
+    /// Set while the current recording is paused. Input is left out until it's resumed.
+    paused: AtomicBool,
+    /// Set once Talky is shutting down, so the recording thread stops
+    shut_down: AtomicBool,
+    /// Profile of the current (or last) recording
+    profile: Mutex<String>,
+    /// Profile used when recording is started without naming one, from the TUI or `talky ctl`
+    selected: Mutex<String>,
+    /// Input is replaced with silence until then, so sound cues aren't recorded
+    muted_until: Mutex<Option<Instant>>,
+    /// Peak input level of the latest audio buffer, 0.0 to 1.0, stored as f32 bits
+    level: AtomicU32,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self {
+            recording: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            hands_free: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            shut_down: AtomicBool::new(false),
+            profile: Mutex::new(DEFAULT_PROFILE.to_string()),
+            selected: Mutex::new(DEFAULT_PROFILE.to_string()),
+            muted_until: Mutex::new(None),
+            level: AtomicU32::new(0),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::SeqCst)
+    }
+
+    /// Starts a recording with the given profile, or stops the current recording.
+    /// Returns true if recording started.
+    pub fn toggle(&self, profile: &str) -> bool {
+        let mut current_profile = self.profile.lock().unwrap();
+        let new_state = !self.recording.load(Ordering::SeqCst);
+        if new_state {
+            *current_profile = profile.to_string();
+            self.cancelled.store(false, Ordering::SeqCst);
+        }
+        self.hands_free.store(false, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+        self.recording.store(new_state, Ordering::SeqCst);
+        new_state
+    }
+
+    /// Starts a hands-free recording with the given profile, which goes on until it's
+    /// stopped. Returns false if already recording.
+    pub fn start_hands_free(&self, profile: &str) -> bool {
+        let mut current_profile = self.profile.lock().unwrap();
+        if self.recording.load(Ordering::SeqCst) {
+            return false;
+        }
+        *current_profile = profile.to_string();
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+        self.hands_free.store(true, Ordering::SeqCst);
+        self.recording.store(true, Ordering::SeqCst);
+        true
+    }
+
+    pub fn is_hands_free(&self) -> bool {
+        self.hands_free.load(Ordering::SeqCst)
+    }
+
+    /// Stops the current recording and marks it to be thrown away.
+    /// Returns false if nothing was being recorded.
+    pub fn cancel(&self) -> bool {
+        let _profile = self.profile.lock().unwrap();
+        if !self.recording.load(Ordering::SeqCst) {
+            return false;
+        }
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.hands_free.store(false, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+        self.recording.store(false, Ordering::SeqCst);
+        true
+    }
+
+    /// Tells the recording thread to finish the current recording, if any, and stop
+    pub fn shut_down(&self) {
+        let _profile = self.profile.lock().unwrap();
+        self.shut_down.store(true, Ordering::SeqCst);
+        self.hands_free.store(false, Ordering::SeqCst);
+        self.paused.store(false, Ordering::SeqCst);
+        self.recording.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down.load(Ordering::SeqCst)
+    }
+
+    /// Pauses or resumes the current recording. Returns whether it is now paused, or
+    /// None if nothing is being recorded.
+    pub fn toggle_pause(&self) -> Option<bool> {
+        let _profile = self.profile.lock().unwrap();
+        if !self.recording.load(Ordering::SeqCst) {
+            return None;
+        }
+        let paused = !self.paused.load(Ordering::SeqCst);
+        self.paused.store(paused, Ordering::SeqCst);
+        Some(paused)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the last recording was cancelled, and clears the flag
+    pub fn take_cancelled(&self) -> bool {
+        self.cancelled.swap(false, Ordering::SeqCst)
+    }
+
+    /// Mutes the microphone for the given time, extending a mute already in effect
+    pub fn mute_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut muted_until = self.muted_until.lock().unwrap();
+        if muted_until.is_none_or(|current| current < until) {
+            *muted_until = Some(until);
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted_until.lock().unwrap().is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Input level of the latest audio buffer, 0.0 to 1.0. Only updated while recording.
+    pub fn level(&self) -> f32 {
+        f32::from_bits(self.level.load(Ordering::Relaxed))
+    }
+
+    pub fn set_level(&self, level: f32) {
+        self.level.store(level.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Profile the current recording was started with
+    pub fn profile(&self) -> String {
+        self.profile.lock().unwrap().clone()
+    }
+
+    /// Profile to record with when none is given
+    pub fn selected_profile(&self) -> String {
+        self.selected.lock().unwrap().clone()
+    }
+
+    pub fn select_profile(&self, profile: &str) {
+        *self.selected.lock().unwrap() = profile.to_string();
+    }
+}
+
+impl Default for Control {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat};
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use crate::config::{CuesConfig, SharedConfig};
+use crate::control::Control;
+use crate::events::AppEvent;
+use log::*;
+
+/// Extra time the microphone stays muted after a cue, for the sound to die down
+const MUTE_MARGIN: Duration = Duration::from_millis(150);
+
+/// Time for the last buffer to be heard before the output stream is dropped
+const DRAIN: Duration = Duration::from_millis(100);
+
+/// Length of the fade in and out of each tone, so they don't click
+const FADE_SECONDS: f32 = 0.005;
+
+#[derive(Debug, Clone, Copy)]
+enum Cue {
+    Start,
+    Stop,
+    Cancel,
+    Success,
+    Error,
+}
+
+impl Cue {
+    // (frequency in Hz, length in seconds) of each built-in tone, played one after another
+    fn tones(self) -> &'static [(f32, f32)] {
+        match self {
+            Cue::Start => &[(660.0, 0.07), (880.0, 0.09)],
+            Cue::Stop => &[(880.0, 0.07), (660.0, 0.09)],
+            Cue::Cancel => &[(440.0, 0.08), (330.0, 0.12)],
+            Cue::Success => &[(1320.0, 0.06)],
+            Cue::Error => &[(220.0, 0.12), (0.0, 0.06), (220.0, 0.12)],
+        }
+    }
+
+    // Only the start cue plays as a recording begins. The others can play while a new
+    // recording is going, and muting then would cut off what is being said.
+    fn mutes(self) -> bool {
+        matches!(self, Cue::Start)
+    }
+
+    fn file(self, config: &CuesConfig) -> Option<&Path> {
+        match self {
+            Cue::Start => config.start.as_deref(),
+            Cue::Stop => config.stop.as_deref(),
+            Cue::Cancel => config.cancel.as_deref(),
+            Cue::Success => config.success.as_deref(),
+            Cue::Error => config.error.as_deref(),
+        }
+    }
+}
+
+/// Plays a sound when recording starts, stops or is cancelled and when a transcription
+/// is output or fails, in a background thread. While the start cue plays the microphone
+/// is muted, so it doesn't end up at the beginning of the recording.
+pub fn play(control: Arc<Control>, app_config: SharedConfig, events: mpsc::Receiver<AppEvent>) {
+    thread::spawn(move || {
+        for event in events {
+            let cue = match event {
+                AppEvent::RecordingStarted { .. } => Cue::Start,
+                AppEvent::RecordingStopped => Cue::Stop,
+                AppEvent::RecordingCancelled => Cue::Cancel,
+                AppEvent::Delivered { .. } => Cue::Success,
+                AppEvent::TranscriptionFailed { .. } | AppEvent::Error { .. } => Cue::Error,
+                _ => continue,
+            };
+
+            let config = app_config.load();
+            if !config.0.cues.enabled {
+                continue;
+            }
+            if let Err(e) = play_cue(cue, &config.0.cues, &control) {
+                warn!("Failed to play {:?} cue: {:#}", cue, e);
+            }
+        }
+    });
+}
+
+fn play_cue(cue: Cue, config: &CuesConfig, control: &Arc<Control>) -> Result<()> {
+    let device = cpal::default_host().default_output_device()
+        .context("No audio output device")?;
+    let output_config = device.default_output_config()
+        .context("Failed to get default output config")?;
+    let sample_rate = output_config.sample_rate().0;
+    let channels = output_config.channels() as usize;
+
+    let samples = match cue.file(config) {
+        Some(path) => load_wav(path, sample_rate).unwrap_or_else(|e| {
+            warn!("Playing the built-in tone instead: {:#}", e);
+            synthesize(cue.tones(), sample_rate)
+        }),
+        None => synthesize(cue.tones(), sample_rate),
+    };
+    let samples: Vec<f32> = samples.into_iter().map(|s| s * config.volume).collect();
+    let duration = Duration::from_secs_f32(samples.len() as f32 / sample_rate as f32);
+
+    let cue_output = CueOutput {
+        samples: Arc::new(samples),
+        channels,
+        sample_rate,
+        mute: cue.mutes().then(|| control.clone()),
+    };
+    let (done, finished) = mpsc::channel();
+    let err_fn = |err| error!("An error occurred on the output audio stream: {}", err);
+    let stream = match output_config.sample_format() {
+        SampleFormat::F32 => build_output::<f32>(&device, &output_config.config(), cue_output, done, err_fn),
+        SampleFormat::I16 => build_output::<i16>(&device, &output_config.config(), cue_output, done, err_fn),
+        SampleFormat::U16 => build_output::<u16>(&device, &output_config.config(), cue_output, done, err_fn),
+        _ => return Err(anyhow::anyhow!("Unsupported sample format")),
+    }.context("Failed to build output stream")?;
+
+    stream.play()?;
+    // The stream can take a moment to start, so wait for the callback to use up the
+    // samples rather than for the cue's length
+    if finished.recv_timeout(duration + Duration::from_secs(1)).is_err() {
+        warn!("The {:?} cue didn't finish playing", cue);
+    }
+    thread::sleep(DRAIN);
+    Ok(())
+}
+
+/// A cue ready to be played, and the microphone to mute while it is
+struct CueOutput {
+    samples: Arc<Vec<f32>>,
+    channels: usize,
+    sample_rate: u32,
+    mute: Option<Arc<Control>>,
+}
+
+fn build_output<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    cue: CueOutput,
+    done: mpsc::Sender<()>,
+    err_fn: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: Sample + cpal::SizedSample + FromSample<f32>,
+{
+    let CueOutput { samples, channels, sample_rate, mute } = cue;
+    let mut position = 0;
+    let mut finished = false;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &_| {
+            if position < samples.len() {
+                if let Some(control) = &mute {
+                    // The buffer is heard a little after it's filled, so the mute lasts
+                    // past it until the sound has died down
+                    let frames = data.len() / channels;
+                    control.mute_for(Duration::from_secs_f32(frames as f32 / sample_rate as f32) + MUTE_MARGIN);
+                }
+            } else if !finished {
+                finished = true;
+                let _ = done.send(());
+            }
+
+            // The cue is mono, play it on every channel
+            for frame in data.chunks_mut(channels) {
+                let sample = samples.get(position).copied().unwrap_or(0.0);
+                position += 1;
+                for out in frame {
+                    *out = T::from_sample(sample);
+                }
+            }
+        },
+        err_fn,
+        None,
+    )
+}
+
+// Sine tones with a short fade at each end. A frequency of 0 is a pause.
+fn synthesize(tones: &[(f32, f32)], sample_rate: u32) -> Vec<f32> {
+    let rate = sample_rate as f32;
+    let mut samples = Vec::new();
+    for &(frequency, seconds) in tones {
+        let count = (seconds * rate) as usize;
+        let fade = ((FADE_SECONDS * rate) as usize).max(1);
+        for i in 0..count {
+            if frequency == 0.0 {
+                samples.push(0.0);
+                continue;
+            }
+            let envelope = (i.min(count - 1 - i) as f32 / fade as f32).min(1.0);
+            let phase = 2.0 * std::f32::consts::PI * frequency * i as f32 / rate;
+            samples.push(phase.sin() * envelope * 0.5);
+        }
+    }
+    samples
+}
+
+// Reads a WAV file as mono samples at the given rate
+fn load_wav(path: &Path, sample_rate: u32) -> Result<Vec<f32>> {
+    let reader = hound::WavReader::open(path)
+        .context(format!("Failed to open cue file: {}", path.display()))?;
+    let spec = reader.spec();
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader.into_samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        },
+    };
+
+    // Mix down to mono
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = samples.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok(resample(&mono, spec.sample_rate, sample_rate))
+}
+
+// Linear interpolation is plenty for short cues
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    let count = (samples.len() as f64 / ratio) as usize;
+    (0..count).map(|i| {
+        let position = i as f64 * ratio;
+        let index = (position as usize).min(samples.len() - 1);
+        let next = samples.get(index + 1).copied().unwrap_or(samples[index]);
+        let fraction = (position - index as f64) as f32;
+        samples[index] + (next - samples[index]) * fraction
+    }).collect()
+}
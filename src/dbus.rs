@@ -0,0 +1,239 @@
+use anyhow::{Context, Result};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use zbus::blocking::connection;
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface};
+use crate::config::SharedConfig;
+use crate::control::Control;
+use crate::events::AppEvent;
+use crate::hotkeys::{ListenerEvent, ListenerProxy};
+use log::*;
+
+const BUS_NAME: &str = "org.talky.Dictation";
+const OBJECT_PATH: &str = "/org/talky/Dictation";
+
+/// The org.talky.Dictation object on the session bus. Like `talky ctl`, it drives
+/// recording by sending commands to the hotkey listener.
+struct Dictation {
+    control: Arc<Control>,
+    app_config: SharedConfig,
+    listener: ListenerProxy,
+}
+
+#[interface(name = "org.talky.Dictation")]
+impl Dictation {
+    /// Starts recording with the selected profile
+    fn start(&self) -> fdo::Result<()> {
+        self.start_recording(self.control.selected_profile())
+    }
+
+    /// Starts recording with the named profile
+    fn start_with_profile(&self, profile: &str) -> fdo::Result<()> {
+        self.start_recording(self.profile(profile)?)
+    }
+
+    fn stop(&self) -> fdo::Result<()> {
+        if !self.control.is_recording() {
+            return Ok(());
+        }
+        self.send(ListenerEvent::Stop)
+    }
+
+    /// Starts recording with the selected profile, or stops recording
+    fn toggle(&self) -> fdo::Result<()> {
+        self.send(ListenerEvent::Toggle(self.control.selected_profile()))
+    }
+
+    /// Starts recording with the named profile, or stops recording
+    fn toggle_with_profile(&self, profile: &str) -> fdo::Result<()> {
+        let profile = self.profile(profile)?;
+        self.send(ListenerEvent::Toggle(profile))
+    }
+
+    fn cancel(&self) -> fdo::Result<()> {
+        if !self.control.is_recording() {
+            return Ok(());
+        }
+        self.send(ListenerEvent::Cancel)
+    }
+
+    /// "recording" or "idle"
+    #[zbus(property)]
+    fn state(&self) -> String {
+        if self.control.is_recording() { "recording" } else { "idle" }.to_string()
+    }
+
+    #[zbus(signal)]
+    async fn transcription_ready(emitter: &SignalEmitter<'_>, text: &str, profile: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn error(emitter: &SignalEmitter<'_>, message: &str) -> zbus::Result<()>;
+}
+
+impl Dictation {
+    fn start_recording(&self, profile: String) -> fdo::Result<()> {
+        if self.control.is_recording() {
+            return Ok(());
+        }
+        self.send(ListenerEvent::Start(profile))
+    }
+
+    fn profile(&self, name: &str) -> fdo::Result<String> {
+        if self.app_config.load().0.profile(name).is_none() {
+            return Err(fdo::Error::InvalidArgs(format!("Profile '{}' not found", name)));
+        }
+        Ok(name.to_string())
+    }
+
+    fn send(&self, event: ListenerEvent) -> fdo::Result<()> {
+        self.listener.send_event(event).map_err(|e| fdo::Error::Failed(e.to_string()))
+    }
+}
+
+/// Publishes org.talky.Dictation on the session bus and turns app events into its
+/// signals and State changes in a background thread
+pub fn serve(control: Arc<Control>, app_config: SharedConfig, listener: ListenerProxy, events: mpsc::Receiver<AppEvent>) -> Result<()> {
+    serve_on(connection::Builder::session()?, control, app_config, listener, events)
+}
+
+fn serve_on(
+    builder: connection::Builder,
+    control: Arc<Control>,
+    app_config: SharedConfig,
+    listener: ListenerProxy,
+    events: mpsc::Receiver<AppEvent>,
+) -> Result<()> {
+    let dictation = Dictation { control, app_config, listener };
+    let connection = builder
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, dictation)?
+        .build()
+        .context("Failed to register on the session bus")?;
+    debug!("Serving {} on the session bus", BUS_NAME);
+
+    let interface = connection.object_server().interface::<_, Dictation>(OBJECT_PATH)?;
+
+    thread::spawn(move || {
+        // The name is released when the connection is dropped
+        let _connection = connection;
+
+        for event in events {
+            let emitter = interface.signal_emitter();
+            let result = match event {
+                AppEvent::RecordingStarted { .. } | AppEvent::RecordingStopped | AppEvent::RecordingCancelled => {
+                    zbus::block_on(interface.get().state_changed(emitter))
+                },
+                AppEvent::Delivered { text, profile, .. } => {
+                    zbus::block_on(Dictation::transcription_ready(emitter, &text, &profile))
+                },
+                AppEvent::TranscriptionFailed { error: message, .. } | AppEvent::Error { message } => {
+                    zbus::block_on(Dictation::error(emitter, &message))
+                },
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                warn!("Failed to send D-Bus signal: {:?}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arc_swap::ArcSwap;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::time::Duration;
+    use zbus::blocking::{Connection, MessageIterator};
+    use zbus::MatchRule;
+    use crate::config::{ApiKeyConfig, Config, DEFAULT_CONFIG, DEFAULT_PROFILE};
+    use crate::evdev_keys::Input;
+    use crate::events::Events;
+
+    /// A dbus-daemon of our own, so the test doesn't touch the desktop session
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address=1"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+            Some(Self { daemon, address: address.trim().to_string() })
+        }
+
+        fn connect(&self) -> Connection {
+            connection::Builder::address(self.address.as_str()).unwrap().build().unwrap()
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[test]
+    fn methods_and_signals_on_a_private_bus() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon isn't available, skipping");
+            return;
+        };
+
+        let control = Arc::new(Control::new());
+        let config = Config::parse_with_overrides(DEFAULT_CONFIG, &Default::default()).unwrap();
+        let app_config = Arc::new(ArcSwap::from_pointee((config, ApiKeyConfig::default())));
+        let (sender, inputs) = mpsc::channel();
+        let events = Events::new();
+        let builder = connection::Builder::address(bus.address.as_str()).unwrap();
+        serve_on(builder, control.clone(), app_config, ListenerProxy::Evdev(sender), events.subscribe()).unwrap();
+
+        let client = bus.connect();
+        let rule = MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .interface(BUS_NAME).unwrap()
+            .member("TranscriptionReady").unwrap()
+            .build();
+        let mut signals = MessageIterator::for_match_rule(rule, &client, None).unwrap();
+
+        // Methods are passed on to the hotkey listener
+        client.call_method(Some(BUS_NAME), OBJECT_PATH, Some(BUS_NAME), "Start", &()).unwrap();
+        match inputs.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Input::Event(ListenerEvent::Start(profile)) => assert_eq!(profile, control.selected_profile()),
+            other => panic!("unexpected input {:?}", other),
+        }
+        client.call_method(Some(BUS_NAME), OBJECT_PATH, Some(BUS_NAME), "ToggleWithProfile", &(DEFAULT_PROFILE)).unwrap();
+        match inputs.recv_timeout(Duration::from_secs(5)).unwrap() {
+            Input::Event(ListenerEvent::Toggle(profile)) => assert_eq!(profile, DEFAULT_PROFILE),
+            other => panic!("unexpected input {:?}", other),
+        }
+        let unknown = client.call_method(Some(BUS_NAME), OBJECT_PATH, Some(BUS_NAME), "StartWithProfile", &("nope"));
+        assert!(unknown.is_err());
+
+        let state: zbus::zvariant::OwnedValue = client
+            .call_method(Some(BUS_NAME), OBJECT_PATH, Some("org.freedesktop.DBus.Properties"), "Get", &(BUS_NAME, "State"))
+            .unwrap()
+            .body()
+            .deserialize()
+            .unwrap();
+        assert_eq!(String::try_from(state).unwrap(), "idle");
+
+        // Delivered transcriptions become signals with the final text
+        events.emit(AppEvent::Delivered { seq: 0, late: false, profile: "default".to_string(), text: "Hello.".to_string() });
+        let message = signals.next().unwrap().unwrap();
+        let (text, profile): (String, String) = message.body().deserialize().unwrap();
+        assert_eq!(text, "Hello.");
+        assert_eq!(profile, "default");
+    }
+}
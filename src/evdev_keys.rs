@@ -0,0 +1,490 @@
+use anyhow::{bail, Context, Result};
+use evdev::{Device, EventSummary, KeyCode};
+use global_hotkey::hotkey::{Code, HotKey, Modifiers};
+use std::collections::{BTreeMap, BTreeSet};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use crate::hotkeys::ListenerEvent;
+use log::*;
+
+/// What the evdev listener waits for
+#[derive(Debug)]
+pub enum Input {
+    /// An event from another thread, see `ListenerProxy`
+    Event(ListenerEvent),
+    /// The registered hotkey with this id was pressed
+    Pressed(u32),
+    /// The key of a pressed hotkey was let go
+    Released(u32),
+}
+
+/// The hotkeys the evdev readers look for. Unlike global-hotkey nothing is grabbed, so
+/// the keys also reach the focused app.
+#[derive(Debug, Clone, Default)]
+pub struct EvdevHotkeys {
+    hotkeys: Arc<Mutex<Vec<HotKey>>>,
+}
+
+impl EvdevHotkeys {
+    pub fn register(&self, hotkey: HotKey) -> Result<()> {
+        let mut hotkeys = self.hotkeys.lock().unwrap();
+        if hotkeys.contains(&hotkey) {
+            bail!("Hotkey is already registered");
+        }
+        hotkeys.push(hotkey);
+        Ok(())
+    }
+
+    pub fn unregister(&self, hotkey: HotKey) {
+        self.hotkeys.lock().unwrap().retain(|h| *h != hotkey);
+    }
+
+    fn pressed(&self, modifiers: Modifiers, code: Code) -> Vec<u32> {
+        self.hotkeys.lock().unwrap()
+            .iter()
+            .filter(|h| h.matches(modifiers, code))
+            .map(|h| h.id())
+            .collect()
+    }
+}
+
+/// Where the kernel puts input devices
+const INPUT_DIR: &str = "/dev/input";
+
+/// Starts a thread per keyboard that reads its key events and sends matching hotkey
+/// presses and releases. With no `devices` every keyboard in /dev/input is used, and
+/// keyboards plugged in later are picked up too.
+pub fn start(devices: &[PathBuf], hotkeys: EvdevHotkeys, sender: mpsc::Sender<Input>) -> Result<()> {
+    let keyboards = open_keyboards(devices)?;
+
+    let readers = Readers { hotkeys, sender, held: HeldModifiers::default(), reading: Default::default() };
+    for (path, device) in keyboards {
+        readers.spawn(path, device);
+    }
+
+    if devices.is_empty() {
+        if let Err(e) = watch_hotplug(readers) {
+            warn!("Keyboards plugged in from now on won't be used: {:#}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Opens the given devices, or every keyboard in /dev/input if there are none
+pub fn open_keyboards(devices: &[PathBuf]) -> Result<Vec<(PathBuf, Device)>> {
+    let keyboards: Vec<(PathBuf, Device)> = if devices.is_empty() {
+        evdev::enumerate().filter(|(_, device)| is_keyboard(device)).collect()
+    } else {
+        devices.iter()
+            .map(|path| Device::open(path).map(|d| (path.clone(), d)).context(format!("Failed to open {}", path.display())))
+            .collect::<Result<_>>()?
+    };
+
+    if keyboards.is_empty() {
+        bail!("No keyboards found in /dev/input. Reading them usually needs membership of the 'input' group.");
+    }
+    Ok(keyboards)
+}
+
+/// Whether the hotkey's key is one the readers know, so it can be pressed at all
+pub fn can_press(hotkey: &HotKey) -> bool {
+    let modifiers = [Modifiers::SHIFT, Modifiers::CONTROL, Modifiers::ALT, Modifiers::SUPER];
+    let combinations: Vec<Modifiers> = (0..1 << modifiers.len())
+        .map(|bits| modifiers.iter().enumerate()
+            .filter(|(i, _)| bits & (1 << i) != 0)
+            .fold(Modifiers::empty(), |all, (_, modifier)| all | *modifier))
+        .collect();
+
+    // 0x2ff is KEY_MAX in linux/input-event-codes.h
+    (0..=0x2ff)
+        .filter_map(|value| code(KeyCode::new(value)))
+        .any(|key| combinations.iter().any(|mods| hotkey.matches(mods, key)))
+}
+
+/// Watches /dev/input for new keyboards and starts reading them
+fn watch_hotplug(readers: Readers) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(Path::new(INPUT_DIR), RecursiveMode::NonRecursive)
+        .context(format!("Failed to watch {}", INPUT_DIR))?;
+
+    thread::spawn(move || {
+        // The watcher stops when dropped, so keep it alive for as long as this thread runs
+        let _watcher = watcher;
+
+        for event in receiver {
+            let event = match event {
+                // udev changes the permissions after creating the device, so a device
+                // that can't be opened yet is tried again then
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(ModifyKind::Metadata(_))) => event,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!("{} watcher error: {:?}", INPUT_DIR, e);
+                    continue;
+                },
+            };
+
+            for path in event.paths {
+                let is_event_device = path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("event"));
+                if !is_event_device || readers.is_reading(&path) {
+                    continue;
+                }
+                match Device::open(&path) {
+                    Ok(device) if is_keyboard(&device) => readers.spawn(path, device),
+                    Ok(_) => {},
+                    Err(e) => debug!("Can't open {} yet: {}", path.display(), e),
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Modifier keys held down, by keyboard. They count on every keyboard, so Ctrl on the
+/// laptop's keyboard and a key on an external one still make Ctrl+key.
+#[derive(Debug, Clone, Default)]
+struct HeldModifiers {
+    keys: Arc<Mutex<BTreeSet<(PathBuf, u16)>>>,
+}
+
+impl HeldModifiers {
+    fn press(&self, keyboard: &Path, key: u16) {
+        self.keys.lock().unwrap().insert((keyboard.to_path_buf(), key));
+    }
+
+    fn release(&self, keyboard: &Path, key: u16) {
+        self.keys.lock().unwrap().remove(&(keyboard.to_path_buf(), key));
+    }
+
+    // A keyboard that was unplugged can't release its keys any more
+    fn release_all(&self, keyboard: &Path) {
+        self.keys.lock().unwrap().retain(|(k, _)| k != keyboard);
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        self.keys.lock().unwrap()
+            .iter()
+            .filter_map(|&(_, k)| modifier(KeyCode::new(k)))
+            .fold(Modifiers::empty(), |all, m| all | m)
+    }
+}
+
+/// What every keyboard's reader thread shares
+#[derive(Debug, Clone)]
+struct Readers {
+    hotkeys: EvdevHotkeys,
+    sender: mpsc::Sender<Input>,
+    held: HeldModifiers,
+    /// The keyboards being read, so one plugged in again isn't read twice
+    reading: Arc<Mutex<BTreeSet<PathBuf>>>,
+}
+
+impl Readers {
+    fn is_reading(&self, path: &Path) -> bool {
+        self.reading.lock().unwrap().contains(path)
+    }
+
+    fn spawn(&self, path: PathBuf, mut device: Device) {
+        if !self.reading.lock().unwrap().insert(path.clone()) {
+            return;
+        }
+        info!("Reading hotkeys from {} ({})", path.display(), device.name().unwrap_or("unnamed"));
+        let readers = self.clone();
+        thread::spawn(move || {
+            let mut keys = KeyReader::new(path.clone(), readers.hotkeys.clone(), readers.held.clone());
+            if let Err(e) = read_keys(&mut device, &mut keys, &readers.sender) {
+                warn!("Stopped reading {}: {:?}", path.display(), e);
+            }
+            readers.reading.lock().unwrap().remove(&path);
+        });
+    }
+}
+
+fn is_keyboard(device: &Device) -> bool {
+    device.supported_keys().is_some_and(|keys| {
+        keys.contains(KeyCode::KEY_A) && keys.contains(KeyCode::KEY_Z) && keys.contains(KeyCode::KEY_ENTER)
+    })
+}
+
+fn read_keys(device: &mut Device, keys: &mut KeyReader, sender: &mpsc::Sender<Input>) -> Result<()> {
+    loop {
+        for event in device.fetch_events()? {
+            let EventSummary::Key(_, key, value) = event.destructure() else {
+                continue;
+            };
+            for input in keys.key(key, value) {
+                if sender.send(input).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Turns one keyboard's key events into hotkey presses and releases
+struct KeyReader {
+    keyboard: PathBuf,
+    hotkeys: EvdevHotkeys,
+    held: HeldModifiers,
+    /// Other keys held down, with the hotkeys they pressed
+    down: BTreeMap<u16, Vec<u32>>,
+}
+
+impl KeyReader {
+    fn new(keyboard: PathBuf, hotkeys: EvdevHotkeys, held: HeldModifiers) -> Self {
+        Self { keyboard, hotkeys, held, down: BTreeMap::new() }
+    }
+
+    // `value` is 1 for a press, 0 for a release and 2 for auto-repeat, which is ignored
+    fn key(&mut self, key: KeyCode, value: i32) -> Vec<Input> {
+        if modifier(key).is_some() {
+            match value {
+                1 => self.held.press(&self.keyboard, key.code()),
+                0 => self.held.release(&self.keyboard, key.code()),
+                _ => {},
+            }
+            return Vec::new();
+        }
+
+        match value {
+            1 => {
+                let Some(code) = code(key) else {
+                    return Vec::new();
+                };
+                let ids = self.hotkeys.pressed(self.held.modifiers(), code);
+                self.down.insert(key.code(), ids.clone());
+                ids.into_iter().map(Input::Pressed).collect()
+            },
+            0 => self.down.remove(&key.code()).unwrap_or_default().into_iter().map(Input::Released).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Drop for KeyReader {
+    fn drop(&mut self) {
+        self.held.release_all(&self.keyboard);
+    }
+}
+
+fn modifier(key: KeyCode) -> Option<Modifiers> {
+    match key {
+        KeyCode::KEY_LEFTSHIFT | KeyCode::KEY_RIGHTSHIFT => Some(Modifiers::SHIFT),
+        KeyCode::KEY_LEFTCTRL | KeyCode::KEY_RIGHTCTRL => Some(Modifiers::CONTROL),
+        KeyCode::KEY_LEFTALT | KeyCode::KEY_RIGHTALT => Some(Modifiers::ALT),
+        KeyCode::KEY_LEFTMETA | KeyCode::KEY_RIGHTMETA => Some(Modifiers::SUPER),
+        _ => None,
+    }
+}
+
+// The key a hotkey string names, for the keys on a usual keyboard
+fn code(key: KeyCode) -> Option<Code> {
+    let code = match key {
+        KeyCode::KEY_A => Code::KeyA,
+        KeyCode::KEY_B => Code::KeyB,
+        KeyCode::KEY_C => Code::KeyC,
+        KeyCode::KEY_D => Code::KeyD,
+        KeyCode::KEY_E => Code::KeyE,
+        KeyCode::KEY_F => Code::KeyF,
+        KeyCode::KEY_G => Code::KeyG,
+        KeyCode::KEY_H => Code::KeyH,
+        KeyCode::KEY_I => Code::KeyI,
+        KeyCode::KEY_J => Code::KeyJ,
+        KeyCode::KEY_K => Code::KeyK,
+        KeyCode::KEY_L => Code::KeyL,
+        KeyCode::KEY_M => Code::KeyM,
+        KeyCode::KEY_N => Code::KeyN,
+        KeyCode::KEY_O => Code::KeyO,
+        KeyCode::KEY_P => Code::KeyP,
+        KeyCode::KEY_Q => Code::KeyQ,
+        KeyCode::KEY_R => Code::KeyR,
+        KeyCode::KEY_S => Code::KeyS,
+        KeyCode::KEY_T => Code::KeyT,
+        KeyCode::KEY_U => Code::KeyU,
+        KeyCode::KEY_V => Code::KeyV,
+        KeyCode::KEY_W => Code::KeyW,
+        KeyCode::KEY_X => Code::KeyX,
+        KeyCode::KEY_Y => Code::KeyY,
+        KeyCode::KEY_Z => Code::KeyZ,
+        KeyCode::KEY_0 => Code::Digit0,
+        KeyCode::KEY_1 => Code::Digit1,
+        KeyCode::KEY_2 => Code::Digit2,
+        KeyCode::KEY_3 => Code::Digit3,
+        KeyCode::KEY_4 => Code::Digit4,
+        KeyCode::KEY_5 => Code::Digit5,
+        KeyCode::KEY_6 => Code::Digit6,
+        KeyCode::KEY_7 => Code::Digit7,
+        KeyCode::KEY_8 => Code::Digit8,
+        KeyCode::KEY_9 => Code::Digit9,
+        KeyCode::KEY_F1 => Code::F1,
+        KeyCode::KEY_F2 => Code::F2,
+        KeyCode::KEY_F3 => Code::F3,
+        KeyCode::KEY_F4 => Code::F4,
+        KeyCode::KEY_F5 => Code::F5,
+        KeyCode::KEY_F6 => Code::F6,
+        KeyCode::KEY_F7 => Code::F7,
+        KeyCode::KEY_F8 => Code::F8,
+        KeyCode::KEY_F9 => Code::F9,
+        KeyCode::KEY_F10 => Code::F10,
+        KeyCode::KEY_F11 => Code::F11,
+        KeyCode::KEY_F12 => Code::F12,
+        KeyCode::KEY_F13 => Code::F13,
+        KeyCode::KEY_F14 => Code::F14,
+        KeyCode::KEY_F15 => Code::F15,
+        KeyCode::KEY_F16 => Code::F16,
+        KeyCode::KEY_F17 => Code::F17,
+        KeyCode::KEY_F18 => Code::F18,
+        KeyCode::KEY_F19 => Code::F19,
+        KeyCode::KEY_F20 => Code::F20,
+        KeyCode::KEY_F21 => Code::F21,
+        KeyCode::KEY_F22 => Code::F22,
+        KeyCode::KEY_F23 => Code::F23,
+        KeyCode::KEY_F24 => Code::F24,
+        KeyCode::KEY_SPACE => Code::Space,
+        KeyCode::KEY_ENTER => Code::Enter,
+        KeyCode::KEY_TAB => Code::Tab,
+        KeyCode::KEY_ESC => Code::Escape,
+        KeyCode::KEY_BACKSPACE => Code::Backspace,
+        KeyCode::KEY_DELETE => Code::Delete,
+        KeyCode::KEY_INSERT => Code::Insert,
+        KeyCode::KEY_HOME => Code::Home,
+        KeyCode::KEY_END => Code::End,
+        KeyCode::KEY_PAGEUP => Code::PageUp,
+        KeyCode::KEY_PAGEDOWN => Code::PageDown,
+        KeyCode::KEY_UP => Code::ArrowUp,
+        KeyCode::KEY_DOWN => Code::ArrowDown,
+        KeyCode::KEY_LEFT => Code::ArrowLeft,
+        KeyCode::KEY_RIGHT => Code::ArrowRight,
+        KeyCode::KEY_MINUS => Code::Minus,
+        KeyCode::KEY_EQUAL => Code::Equal,
+        KeyCode::KEY_LEFTBRACE => Code::BracketLeft,
+        KeyCode::KEY_RIGHTBRACE => Code::BracketRight,
+        KeyCode::KEY_BACKSLASH => Code::Backslash,
+        KeyCode::KEY_SEMICOLON => Code::Semicolon,
+        KeyCode::KEY_APOSTROPHE => Code::Quote,
+        KeyCode::KEY_GRAVE => Code::Backquote,
+        KeyCode::KEY_COMMA => Code::Comma,
+        KeyCode::KEY_DOT => Code::Period,
+        KeyCode::KEY_SLASH => Code::Slash,
+        KeyCode::KEY_CAPSLOCK => Code::CapsLock,
+        KeyCode::KEY_SCROLLLOCK => Code::ScrollLock,
+        KeyCode::KEY_NUMLOCK => Code::NumLock,
+        KeyCode::KEY_PAUSE => Code::Pause,
+        KeyCode::KEY_SYSRQ => Code::PrintScreen,
+        KeyCode::KEY_KP0 => Code::Numpad0,
+        KeyCode::KEY_KP1 => Code::Numpad1,
+        KeyCode::KEY_KP2 => Code::Numpad2,
+        KeyCode::KEY_KP3 => Code::Numpad3,
+        KeyCode::KEY_KP4 => Code::Numpad4,
+        KeyCode::KEY_KP5 => Code::Numpad5,
+        KeyCode::KEY_KP6 => Code::Numpad6,
+        KeyCode::KEY_KP7 => Code::Numpad7,
+        KeyCode::KEY_KP8 => Code::Numpad8,
+        KeyCode::KEY_KP9 => Code::Numpad9,
+        KeyCode::KEY_KPPLUS => Code::NumpadAdd,
+        KeyCode::KEY_KPMINUS => Code::NumpadSubtract,
+        KeyCode::KEY_KPASTERISK => Code::NumpadMultiply,
+        KeyCode::KEY_KPSLASH => Code::NumpadDivide,
+        KeyCode::KEY_KPDOT => Code::NumpadDecimal,
+        KeyCode::KEY_KPENTER => Code::NumpadEnter,
+        KeyCode::KEY_MUTE => Code::AudioVolumeMute,
+        KeyCode::KEY_VOLUMEDOWN => Code::AudioVolumeDown,
+        KeyCode::KEY_VOLUMEUP => Code::AudioVolumeUp,
+        KeyCode::KEY_PLAYPAUSE => Code::MediaPlayPause,
+        KeyCode::KEY_STOPCD => Code::MediaStop,
+        KeyCode::KEY_NEXTSONG => Code::MediaTrackNext,
+        KeyCode::KEY_PREVIOUSSONG => Code::MediaTrackPrevious,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evdev::uinput::VirtualDevice;
+    use evdev::{AttributeSet, KeyEvent};
+    use std::time::Duration;
+
+    fn ctrl_r() -> (EvdevHotkeys, HotKey) {
+        let hotkeys = EvdevHotkeys::default();
+        let hotkey = HotKey::new(Some(Modifiers::CONTROL), Code::KeyR);
+        hotkeys.register(hotkey).unwrap();
+        (hotkeys, hotkey)
+    }
+
+    #[test]
+    fn presses_and_releases_hotkeys() {
+        let (hotkeys, hotkey) = ctrl_r();
+        let mut keys = KeyReader::new(PathBuf::from("event0"), hotkeys, HeldModifiers::default());
+
+        // Without Ctrl, R is just typing
+        assert!(keys.key(KeyCode::KEY_R, 1).is_empty());
+        assert!(keys.key(KeyCode::KEY_R, 0).is_empty());
+
+        keys.key(KeyCode::KEY_LEFTCTRL, 1);
+        assert!(matches!(keys.key(KeyCode::KEY_R, 1)[..], [Input::Pressed(id)] if id == hotkey.id()));
+        assert!(keys.key(KeyCode::KEY_R, 2).is_empty());
+        // Letting go of Ctrl first still releases the hotkey with R
+        keys.key(KeyCode::KEY_LEFTCTRL, 0);
+        assert!(matches!(keys.key(KeyCode::KEY_R, 0)[..], [Input::Released(id)] if id == hotkey.id()));
+    }
+
+    #[test]
+    fn knows_which_hotkeys_can_be_pressed() {
+        assert!(can_press(&"ctrl+shift+KeyR".parse::<HotKey>().unwrap()));
+        assert!(can_press(&"F24".parse::<HotKey>().unwrap()));
+        assert!(!can_press(&HotKey::new(None, Code::BrowserBack)));
+    }
+
+    #[test]
+    fn modifiers_count_on_every_keyboard() {
+        let (hotkeys, hotkey) = ctrl_r();
+        let held = HeldModifiers::default();
+        let mut laptop = KeyReader::new(PathBuf::from("event0"), hotkeys.clone(), held.clone());
+        let mut external = KeyReader::new(PathBuf::from("event1"), hotkeys, held.clone());
+
+        laptop.key(KeyCode::KEY_RIGHTCTRL, 1);
+        assert!(matches!(external.key(KeyCode::KEY_R, 1)[..], [Input::Pressed(id)] if id == hotkey.id()));
+        external.key(KeyCode::KEY_R, 0);
+
+        // An unplugged keyboard's modifiers are let go
+        drop(laptop);
+        assert!(external.key(KeyCode::KEY_R, 1).is_empty());
+        assert_eq!(held.modifiers(), Modifiers::empty());
+    }
+
+    #[test]
+    fn reads_a_uinput_keyboard() {
+        let keys: AttributeSet<KeyCode> = [KeyCode::KEY_A, KeyCode::KEY_Z, KeyCode::KEY_ENTER, KeyCode::KEY_LEFTCTRL, KeyCode::KEY_R]
+            .into_iter()
+            .collect();
+        let device = VirtualDevice::builder()
+            .and_then(|builder| builder.name("Talky test keyboard").with_keys(&keys))
+            .and_then(|builder| builder.build());
+        let mut device = match device {
+            Ok(device) => device,
+            Err(e) => {
+                eprintln!("uinput isn't available, skipping: {}", e);
+                return;
+            },
+        };
+        let path = device.enumerate_dev_nodes_blocking().unwrap().next().unwrap().unwrap();
+
+        let (hotkeys, hotkey) = ctrl_r();
+        let (sender, receiver) = mpsc::channel();
+        start(std::slice::from_ref(&path), hotkeys, sender).unwrap();
+
+        device.emit(&[*KeyEvent::new(KeyCode::KEY_LEFTCTRL, 1), *KeyEvent::new(KeyCode::KEY_R, 1)]).unwrap();
+        device.emit(&[*KeyEvent::new(KeyCode::KEY_R, 0), *KeyEvent::new(KeyCode::KEY_LEFTCTRL, 0)]).unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert!(matches!(receiver.recv_timeout(timeout), Ok(Input::Pressed(id)) if id == hotkey.id()));
+        assert!(matches!(receiver.recv_timeout(timeout), Ok(Input::Released(id)) if id == hotkey.id()));
+    }
+}
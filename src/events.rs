@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+
+/// Something that happened in the app, for front-ends and integrations to react to
+#[derive(Debug, Clone)]
+pub enum AppEvent {
+    RecordingStarted { profile: String },
+    RecordingStopped,
+    /// The recording was thrown away without being transcribed
+    RecordingCancelled,
+    /// A recording was queued for transcription
+    Queued { seq: u64, profile: String },
+    /// `confidence` is 0.0 to 1.0, if the model reports it
+    Transcribed { seq: u64, profile: String, text: String, latency_ms: u64, confidence: Option<f32> },
+    /// `unreachable` means the API couldn't be reached, rather than rejecting the recording.
+    /// `recording` is where the recording was kept, if it was.
+    TranscriptionFailed { seq: u64, error: String, unreachable: bool, recording: Option<PathBuf> },
+    /// A transcription was output, after post-processing. `late` means it went to
+    /// the late sinks.
+    Delivered { seq: u64, late: bool, profile: String, text: String },
+    /// A transcription was a spoken undo phrase, and took back the one before it
+    Undone { seq: u64 },
+    /// Something other than a transcription failed, such as a sink or the recording
+    Error { message: String },
+}
+
+/// Sends app events to every subscriber. Clones share the same subscribers.
+#[derive(Debug, Clone, Default)]
+pub struct Events {
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<AppEvent>>>>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a receiver that gets every event from now on
+    pub fn subscribe(&self) -> mpsc::Receiver<AppEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Sends an event to all subscribers, dropping the ones that have gone away
+    pub fn emit(&self, event: AppEvent) {
+        self.subscribers.lock().unwrap().retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use crate::config::{GestureActions, GesturesConfig, HotkeyAction};
+
+/// Tells taps, double taps and long presses of hotkeys apart, from their presses and
+/// releases and the time in between. Call `tick` by `deadline` to fire the gestures
+/// that are decided by waiting.
+#[derive(Debug, Default)]
+pub struct Recognizer {
+    /// Hotkeys in the middle of a gesture, by id
+    pending: BTreeMap<u32, Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    actions: GestureActions,
+    state: State,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Pressed at this time. Held long enough it's a long press.
+    Down(Instant),
+    /// Tapped and let go at this time. Pressed again soon enough it's a double tap.
+    Up(Instant),
+    /// A long press or double tap has fired, waiting for the keys to be let go
+    Fired,
+}
+
+impl Recognizer {
+    /// Returns the actions to run now, in order. A tap that was waiting for a double tap
+    /// too long, because `tick` wasn't called in time, comes before the new press.
+    pub fn press(&mut self, id: u32, actions: &GestureActions, config: &GesturesConfig, now: Instant) -> Vec<HotkeyAction> {
+        let (double_tap, _) = timing(config);
+        let mut fired = Vec::new();
+
+        if let Some(pending) = self.pending.get_mut(&id) {
+            if let State::Up(at) = pending.state {
+                if now.saturating_duration_since(at) <= double_tap {
+                    pending.state = State::Fired;
+                    fired.extend(pending.actions.double_tap.clone());
+                    return fired;
+                }
+                fired.extend(self.pending.remove(&id).and_then(|p| p.actions.tap));
+            }
+        }
+
+        // A tap only has to wait if it could turn into something else
+        if actions.double_tap.is_none() && actions.long_press.is_none() {
+            fired.extend(actions.tap.clone());
+            return fired;
+        }
+        self.pending.insert(id, Pending { actions: actions.clone(), state: State::Down(now) });
+        fired
+    }
+
+    /// Returns the action to run now, if the release decides the gesture
+    pub fn release(&mut self, id: u32, now: Instant) -> Option<HotkeyAction> {
+        let pending = self.pending.get_mut(&id)?;
+        match pending.state {
+            State::Down(_) if pending.actions.double_tap.is_some() => {
+                pending.state = State::Up(now);
+                None
+            },
+            State::Down(_) => self.pending.remove(&id).and_then(|p| p.actions.tap),
+            State::Up(_) => None,
+            State::Fired => {
+                self.pending.remove(&id);
+                None
+            },
+        }
+    }
+
+    /// Fires the long presses and taps whose time is up. Returns (hotkey id, action) pairs.
+    pub fn tick(&mut self, config: &GesturesConfig, now: Instant) -> Vec<(u32, HotkeyAction)> {
+        let (double_tap, long_press) = timing(config);
+        let mut fired = Vec::new();
+
+        self.pending.retain(|&id, pending| match pending.state {
+            State::Down(since) if now >= since + long_press => {
+                if let Some(action) = pending.actions.long_press.clone() {
+                    fired.push((id, action));
+                    pending.state = State::Fired;
+                }
+                true
+            },
+            State::Up(at) if now >= at + double_tap => {
+                if let Some(action) = pending.actions.tap.clone() {
+                    fired.push((id, action));
+                }
+                false
+            },
+            _ => true,
+        });
+
+        fired
+    }
+
+    /// When `tick` next has something to do, if anything
+    pub fn deadline(&self, config: &GesturesConfig) -> Option<Instant> {
+        let (double_tap, long_press) = timing(config);
+        self.pending.values()
+            .filter_map(|pending| match pending.state {
+                State::Down(since) if pending.actions.long_press.is_some() => Some(since + long_press),
+                State::Up(at) => Some(at + double_tap),
+                _ => None,
+            })
+            .min()
+    }
+}
+
+fn timing(config: &GesturesConfig) -> (Duration, Duration) {
+    (Duration::from_millis(config.double_tap_ms), Duration::from_millis(config.long_press_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: GesturesConfig = GesturesConfig { double_tap_ms: 300, long_press_ms: 500 };
+
+    fn actions() -> GestureActions {
+        GestureActions {
+            tap: Some(HotkeyAction::Toggle(None)),
+            double_tap: Some(HotkeyAction::Undo),
+            long_press: Some(HotkeyAction::Hold(None)),
+        }
+    }
+
+    fn ms(start: Instant, ms: u64) -> Instant {
+        start + Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn tap_fires_once_the_double_tap_window_is_over() {
+        let mut recognizer = Recognizer::default();
+        let start = Instant::now();
+        assert!(recognizer.press(1, &actions(), &CONFIG, start).is_empty());
+        assert_eq!(recognizer.release(1, ms(start, 50)), None);
+        assert_eq!(recognizer.deadline(&CONFIG), Some(ms(start, 350)));
+        assert!(recognizer.tick(&CONFIG, ms(start, 200)).is_empty());
+        assert_eq!(recognizer.tick(&CONFIG, ms(start, 350)), vec![(1, HotkeyAction::Toggle(None))]);
+        assert_eq!(recognizer.deadline(&CONFIG), None);
+    }
+
+    #[test]
+    fn second_press_in_time_is_a_double_tap() {
+        let mut recognizer = Recognizer::default();
+        let start = Instant::now();
+        recognizer.press(1, &actions(), &CONFIG, start);
+        recognizer.release(1, ms(start, 50));
+        assert_eq!(recognizer.press(1, &actions(), &CONFIG, ms(start, 200)), vec![HotkeyAction::Undo]);
+        assert_eq!(recognizer.release(1, ms(start, 250)), None);
+        assert!(recognizer.tick(&CONFIG, ms(start, 1000)).is_empty());
+    }
+
+    #[test]
+    fn late_second_press_is_a_tap_and_a_new_press() {
+        let mut recognizer = Recognizer::default();
+        let start = Instant::now();
+        recognizer.press(1, &actions(), &CONFIG, start);
+        recognizer.release(1, ms(start, 50));
+        // tick wasn't called in time, so the first tap fires now
+        assert_eq!(recognizer.press(1, &actions(), &CONFIG, ms(start, 600)), vec![HotkeyAction::Toggle(None)]);
+        assert_eq!(recognizer.deadline(&CONFIG), Some(ms(start, 1100)));
+    }
+
+    #[test]
+    fn holding_is_a_long_press() {
+        let mut recognizer = Recognizer::default();
+        let start = Instant::now();
+        recognizer.press(1, &actions(), &CONFIG, start);
+        assert_eq!(recognizer.tick(&CONFIG, ms(start, 500)), vec![(1, HotkeyAction::Hold(None))]);
+        assert_eq!(recognizer.release(1, ms(start, 900)), None);
+        assert!(recognizer.tick(&CONFIG, ms(start, 2000)).is_empty());
+    }
+
+    #[test]
+    fn tap_only_hotkeys_fire_right_away() {
+        let mut recognizer = Recognizer::default();
+        let actions = GestureActions { tap: Some(HotkeyAction::Undo), double_tap: None, long_press: None };
+        assert_eq!(recognizer.press(1, &actions, &CONFIG, Instant::now()), vec![HotkeyAction::Undo]);
+        assert_eq!(recognizer.deadline(&CONFIG), None);
+    }
+}
@@ -0,0 +1,340 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use log::*;
+
+/// One dictation, as stored in the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u64,
+    pub time: DateTime<Local>,
+    pub profile: String,
+    pub model: String,
+    /// Length of the recording
+    pub duration_ms: u64,
+    /// Time the transcription request took
+    pub latency_ms: u64,
+    pub text: String,
+    /// Where the recording was cached, if it was. Old recordings are deleted, so the
+    /// file may be gone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<PathBuf>,
+}
+
+/// Every transcription, appended to a JSON lines file so it survives crashes and can
+/// be read with other tools
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Adds an entry, giving it the next id
+    pub fn append(&self, mut entry: Entry) -> Result<Entry> {
+        let mut lock = self.lock()?;
+
+        // Files written before the lock file kept the last id only have it in the entries
+        let last_id = match lock.last_id() {
+            Some(id) => id,
+            None => self.entries()?.iter().map(|e| e.id).max().unwrap_or(0),
+        };
+        entry.id = last_id + 1;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        lock.set_last_id(entry.id)?;
+
+        Ok(entry)
+    }
+
+    // Talky appends from its delivery thread while `talky history prune` may be
+    // rewriting the file from another process, so both take a file lock first
+    fn lock(&self) -> Result<HistoryLock> {
+        let path = self.path.with_extension("lock");
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .context(format!("Failed to open lock file: {}", path.display()))?;
+        file.lock().context(format!("Failed to lock {}", path.display()))?;
+        Ok(HistoryLock { file })
+    }
+
+    /// All entries, oldest first. Lines that can't be parsed are skipped.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context(format!("Failed to open {}", self.path.display())),
+        };
+
+        let mut entries = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("{}:{}: skipping invalid history entry: {}", self.path.display(), index + 1, e),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// The entry with the given id, or the latest one
+    pub fn find(&self, id: Option<u64>) -> Result<Entry> {
+        let entries = self.entries()?;
+        match id {
+            Some(id) => entries.into_iter().find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("No transcription with id {}", id)),
+            None => entries.into_iter().last().ok_or_else(|| anyhow::anyhow!("The history is empty")),
+        }
+    }
+
+    /// Entries whose text contains every word of the query, ignoring case
+    pub fn search(&self, query: &str) -> Result<Vec<Entry>> {
+        let words: Vec<String> = query.split_whitespace().map(|w| w.to_lowercase()).collect();
+        Ok(self.entries()?
+            .into_iter()
+            .filter(|entry| {
+                let text = entry.text.to_lowercase();
+                words.iter().all(|word| text.contains(word))
+            })
+            .collect())
+    }
+
+    /// Removes entries older than `max_age` and returns how many were removed
+    pub fn prune(&self, max_age: TimeDelta) -> Result<usize> {
+        let _lock = self.lock()?;
+
+        let entries = self.entries()?;
+        let cutoff = Local::now() - max_age;
+        let (keep, removed): (Vec<_>, Vec<_>) = entries.into_iter().partition(|e| e.time >= cutoff);
+        if removed.is_empty() {
+            return Ok(0);
+        }
+
+        // Write the new file next to the old one and swap it in, so a crash can't lose it
+        let temp_path = self.path.with_extension("jsonl.tmp");
+        let mut temp = File::create(&temp_path)
+            .context(format!("Failed to create {}", temp_path.display()))?;
+        for entry in &keep {
+            writeln!(temp, "{}", serde_json::to_string(entry)?)?;
+        }
+        temp.sync_all()?;
+        fs::rename(&temp_path, &self.path)
+            .context(format!("Failed to replace {}", self.path.display()))?;
+
+        Ok(removed.len())
+    }
+}
+
+/// The history's lock file, locked while it's held. It also keeps the last id given
+/// out, so ids aren't reused once the entries holding them are pruned.
+struct HistoryLock {
+    file: File,
+}
+
+impl HistoryLock {
+    /// The last id given out, or None if the lock file doesn't have one yet
+    fn last_id(&mut self) -> Option<u64> {
+        let mut contents = String::new();
+        let _ = self.file.seek(SeekFrom::Start(0)).and_then(|_| self.file.read_to_string(&mut contents));
+        contents.trim().parse().ok()
+    }
+
+    fn set_last_id(&mut self, id: u64) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        write!(self.file, "{}", id)?;
+        Ok(())
+    }
+}
+
+/// Parses an age such as "30d", "12h", "45m" or "90s". A plain number is days.
+pub fn parse_age(value: &str) -> Result<TimeDelta> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "d"),
+    };
+    let number: i64 = number.parse().context(format!("Invalid age '{}', expected e.g. 30d", value))?;
+
+    match unit {
+        "d" => Ok(TimeDelta::days(number)),
+        "h" => Ok(TimeDelta::hours(number)),
+        "m" => Ok(TimeDelta::minutes(number)),
+        "s" => Ok(TimeDelta::seconds(number)),
+        _ => Err(anyhow::anyhow!("Invalid age '{}', the unit must be d, h, m or s", value)),
+    }
+}
+
+/// Export formats for `talky history export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Markdown,
+    Csv,
+    Json,
+}
+
+/// Writes entries in the given format
+pub fn export(entries: &[Entry], format: ExportFormat, out: &mut dyn Write) -> Result<()> {
+    match format {
+        ExportFormat::Markdown => {
+            for entry in entries {
+                writeln!(out, "## {} ({})", entry.time.format("%Y-%m-%d %H:%M:%S"), entry.profile)?;
+                writeln!(out)?;
+                writeln!(out, "{}", entry.text)?;
+                writeln!(out)?;
+            }
+        },
+        ExportFormat::Csv => {
+            writeln!(out, "id,time,profile,model,duration_ms,latency_ms,text")?;
+            for entry in entries {
+                writeln!(out, "{},{},{},{},{},{},{}",
+                    entry.id,
+                    entry.time.to_rfc3339(),
+                    csv_field(&entry.profile),
+                    csv_field(&entry.model),
+                    entry.duration_ms,
+                    entry.latency_ms,
+                    csv_field(&entry.text))?;
+            }
+        },
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, entries)?;
+            writeln!(out)?;
+        },
+    }
+
+    Ok(())
+}
+
+// Quotes a CSV field if needed, doubling any quotes inside it
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Length of a wav file in milliseconds
+pub fn wav_duration_ms(path: &Path) -> Result<u64> {
+    let reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    Ok(reader.duration() as u64 * 1000 / spec.sample_rate.max(1) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_history(name: &str) -> History {
+        let dir = std::env::temp_dir().join(format!("talky-history-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        History::new(dir.join("history.jsonl"))
+    }
+
+    fn entry(text: &str, age: TimeDelta) -> Entry {
+        Entry {
+            id: 0,
+            time: Local::now() - age,
+            profile: "default".to_string(),
+            model: "whisper-1".to_string(),
+            duration_ms: 1000,
+            latency_ms: 200,
+            text: text.to_string(),
+            audio: None,
+        }
+    }
+
+    #[test]
+    fn append_numbers_entries() {
+        let history = temp_history("append");
+        assert_eq!(history.append(entry("one", TimeDelta::zero())).unwrap().id, 1);
+        assert_eq!(history.append(entry("two", TimeDelta::zero())).unwrap().id, 2);
+        let texts: Vec<String> = history.entries().unwrap().into_iter().map(|e| e.text).collect();
+        assert_eq!(texts, ["one", "two"]);
+    }
+
+    #[test]
+    fn prune_removes_old_entries() {
+        let history = temp_history("prune");
+        history.append(entry("old", TimeDelta::days(40))).unwrap();
+        history.append(entry("new", TimeDelta::zero())).unwrap();
+        assert_eq!(history.prune(TimeDelta::days(30)).unwrap(), 1);
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].text, "new");
+        assert_eq!(history.prune(TimeDelta::days(30)).unwrap(), 0);
+    }
+
+    #[test]
+    fn ids_are_not_reused_after_pruning_everything() {
+        let history = temp_history("ids");
+        history.append(entry("one", TimeDelta::days(2))).unwrap();
+        history.append(entry("two", TimeDelta::days(2))).unwrap();
+        assert_eq!(history.prune(TimeDelta::days(1)).unwrap(), 2);
+        assert_eq!(history.append(entry("three", TimeDelta::zero())).unwrap().id, 3);
+    }
+
+    #[test]
+    fn ids_continue_from_a_history_without_a_lock_file() {
+        let history = temp_history("no-lock");
+        history.append(entry("one", TimeDelta::zero())).unwrap();
+        history.append(entry("two", TimeDelta::zero())).unwrap();
+        fs::remove_file(history.path.with_extension("lock")).unwrap();
+        assert_eq!(history.append(entry("three", TimeDelta::zero())).unwrap().id, 3);
+    }
+
+    #[test]
+    fn concurrent_appends_get_unique_ids() {
+        let history = std::sync::Arc::new(temp_history("concurrent"));
+        let threads: Vec<_> = (0..4).map(|_| {
+            let history = history.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    history.append(entry("text", TimeDelta::zero())).unwrap();
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let mut ids: Vec<u64> = history.entries().unwrap().into_iter().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, (1..=40).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("30d").unwrap(), TimeDelta::days(30));
+        assert_eq!(parse_age("12h").unwrap(), TimeDelta::hours(12));
+        assert_eq!(parse_age("7").unwrap(), TimeDelta::days(7));
+        assert!(parse_age("3w").is_err());
+        assert!(parse_age("d").is_err());
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+    }
+}
@@ -1,71 +1,569 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use global_hotkey::{
     hotkey::HotKey,
-    GlobalHotKeyEvent, GlobalHotKeyManager,
+    GlobalHotKeyEvent, GlobalHotKeyManager, HotKeyState,
 };
-use std::{str::FromStr, sync::{atomic::{AtomicBool, Ordering}, Arc}};
-use winit::event_loop::{ControlFlow, EventLoop};
+use std::{str::FromStr, sync::Arc, thread};
+use std::time::Instant;
+use winit::event::Event;
+use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy};
+use crate::config::{HotkeyAction, HotkeyActions, HotkeyBackend, HotkeyBinding, ListenerConfig, SharedConfig, DEFAULT_PROFILE};
+use crate::control::Control;
+use crate::gestures::Recognizer;
+use crate::paste;
+use crate::pipeline::Pipeline;
+use crate::undo::UndoTracker;
 use log::*;
 
+#[cfg(target_os = "linux")]
+use std::sync::mpsc;
+#[cfg(target_os = "linux")]
+use crate::evdev_keys::{self, EvdevHotkeys, Input};
+
+/// Events sent to the hotkey listener from other threads
+#[derive(Debug)]
+pub enum ListenerEvent {
+    /// Replace the registered hotkeys, e.g. after the config was reloaded
+    SetBindings(Vec<HotkeyBinding>),
+    /// Start recording with a profile, or stop the current recording
+    Toggle(String),
+    /// Start recording with a profile, unless already recording
+    Start(String),
+    /// Stop the current recording, if any
+    Stop,
+    /// Throw away the current recording, if any
+    Cancel,
+    /// Finish the current recording, unregister the hotkeys and stop the listener
+    Shutdown,
+}
+
+/// Sends events to the hotkey listener, whichever backend it runs on
+#[derive(Debug, Clone)]
+pub enum ListenerProxy {
+    EventLoop(EventLoopProxy<ListenerEvent>),
+    #[cfg(target_os = "linux")]
+    Evdev(mpsc::Sender<Input>),
+}
+
+impl ListenerProxy {
+    pub fn send_event(&self, event: ListenerEvent) -> Result<()> {
+        let sent = match self {
+            ListenerProxy::EventLoop(proxy) => proxy.send_event(event).is_ok(),
+            #[cfg(target_os = "linux")]
+            ListenerProxy::Evdev(sender) => sender.send(Input::Event(event)).is_ok(),
+        };
+        if !sent {
+            bail!("The hotkey listener has stopped");
+        }
+        Ok(())
+    }
+}
+
+/// Where hotkeys are registered: with the OS through global-hotkey, or with our own
+/// reader of /dev/input
+enum Registry {
+    Global(GlobalHotKeyManager),
+    #[cfg(target_os = "linux")]
+    Evdev(EvdevHotkeys),
+}
+
+impl Registry {
+    fn register(&self, hotkey: HotKey) -> Result<()> {
+        match self {
+            Registry::Global(manager) => manager.register(hotkey)?,
+            #[cfg(target_os = "linux")]
+            Registry::Evdev(hotkeys) => hotkeys.register(hotkey)?,
+        }
+        Ok(())
+    }
+
+    fn unregister(&self, hotkey: HotKey) -> Result<()> {
+        match self {
+            Registry::Global(manager) => manager.unregister(hotkey)?,
+            #[cfg(target_os = "linux")]
+            Registry::Evdev(hotkeys) => hotkeys.unregister(hotkey),
+        }
+        Ok(())
+    }
+}
+
+/// Where the listener gets its events from
+enum Source {
+    /// A winit event loop, which global-hotkey needs on some platforms. Needs a display.
+    EventLoop(Box<EventLoop<ListenerEvent>>),
+    /// A channel fed by the evdev reader threads and by `ListenerProxy`
+    #[cfg(target_os = "linux")]
+    Evdev(mpsc::Sender<Input>, mpsc::Receiver<Input>),
+}
+
+/// A hotkey and its actions. Cancel hotkeys are only registered while recording, so
+/// that keys like Escape still reach other apps the rest of the time.
+#[derive(Debug, Clone)]
+struct Binding {
+    hotkey: HotKey,
+    /// The hotkey as written in config
+    hotkey_string: String,
+    actions: HotkeyActions,
+    /// Config key it was set with, for messages
+    key: String,
+    registered: bool,
+}
+
+impl Binding {
+    fn register(&mut self, registry: &Registry) -> Result<()> {
+        if !self.registered {
+            registry.register(self.hotkey)?;
+            self.registered = true;
+        }
+        Ok(())
+    }
+
+    fn unregister(&mut self, registry: &Registry) {
+        if self.registered {
+            if let Err(e) = registry.unregister(self.hotkey) {
+                warn!("Failed to unregister hotkey {}: {:?}", self.key, e);
+            }
+            self.registered = false;
+        }
+    }
+}
+
 pub struct HotkeyListener {
-    hotkey_manager: GlobalHotKeyManager,
-    recording_state: Arc<AtomicBool>,
-    hotkey_id: u32,
+    registry: Registry,
+    source: Source,
+    control: Arc<Control>,
+    bindings: Vec<Binding>,
+    app_config: SharedConfig,
+    undo_tracker: Arc<UndoTracker>,
+    pipeline: Pipeline,
 }
 
 impl HotkeyListener {
-    pub fn new() -> Result<Self> {
-        let hotkey_manager = GlobalHotKeyManager::new()?;
-        let recording_state = Arc::new(AtomicBool::new(false));
-        
+    pub fn new(
+        listener_config: &ListenerConfig,
+        control: Arc<Control>,
+        app_config: SharedConfig,
+        undo_tracker: Arc<UndoTracker>,
+        pipeline: Pipeline,
+    ) -> Result<Self> {
+        let (registry, source) = match listener_config.backend {
+            HotkeyBackend::GlobalHotkey => {
+                let hotkey_manager = GlobalHotKeyManager::new()?;
+                let event_loop = EventLoopBuilder::with_user_event().build()?;
+                (Registry::Global(hotkey_manager), Source::EventLoop(Box::new(event_loop)))
+            },
+            #[cfg(target_os = "linux")]
+            HotkeyBackend::Evdev => {
+                let hotkeys = EvdevHotkeys::default();
+                let (sender, receiver) = mpsc::channel();
+                evdev_keys::start(&listener_config.devices, hotkeys.clone(), sender.clone())?;
+                (Registry::Evdev(hotkeys), Source::Evdev(sender, receiver))
+            },
+            #[cfg(not(target_os = "linux"))]
+            HotkeyBackend::Evdev => bail!("The evdev hotkey backend only works on Linux"),
+        };
+
         Ok(Self {
-            hotkey_manager,
-            recording_state,
-            hotkey_id: 1, // Default ID
+            registry,
+            source,
+            control,
+            bindings: Vec::new(),
+            app_config,
+            undo_tracker,
+            pipeline,
         })
     }
-    
-    pub fn setup_hotkey(&mut self, hotkey_string: &String) -> Result<()> {
 
-        let hotkey = HotKey::from_str(hotkey_string)?;
-        self.hotkey_id = hotkey.id();
-        self.hotkey_manager.register(hotkey)?;
-        
+    /// Registers the hotkeys. Fails if a key combination is bound to two different actions.
+    pub fn setup_hotkeys(&mut self, bindings: &[HotkeyBinding]) -> Result<()> {
+        let mut bindings = parse_bindings(bindings)?;
+        register_all(&self.registry, &mut bindings, self.control.is_recording())?;
+        self.bindings = bindings;
         Ok(())
     }
-    
-    pub fn get_recording_state(&self) -> Arc<AtomicBool> {
-        self.recording_state.clone()
+
+    /// Returns a handle for sending events to the listener once it is running
+    pub fn proxy(&self) -> ListenerProxy {
+        match &self.source {
+            Source::EventLoop(event_loop) => ListenerProxy::EventLoop(event_loop.create_proxy()),
+            #[cfg(target_os = "linux")]
+            Source::Evdev(sender, _) => ListenerProxy::Evdev(sender.clone()),
+        }
     }
-        
+
     // Runs the hotkey listener event loop. This call will block.
     pub fn run(self) -> Result<()> {
-        let recording_state = self.recording_state.clone();
-        let event_loop = EventLoop::new()?;
-        let hotkey_id = self.hotkey_id;
-        
-        // Important: Create the receiver after registering hotkeys
-        let hotkey_channel = GlobalHotKeyEvent::receiver();
-
-        event_loop.set_control_flow(ControlFlow::Wait);
-        
-        event_loop.run(move |_, _| {
-            
-            if let Ok(event) = hotkey_channel.try_recv() {
-                if event.id == hotkey_id && event.state() == global_hotkey::HotKeyState::Pressed {
-                    // Toggle recording state when hotkey is pressed
-                    let current_state = recording_state.load(Ordering::SeqCst);
-                    let new_state = !current_state;
-                    recording_state.store(new_state, Ordering::SeqCst);
-                    
-                    match new_state {
-                        true => info!("Recording started"),
-                        false => info!("Recording stopped"),
+        let proxy = self.proxy();
+        let HotkeyListener {
+            registry, source, control, bindings, app_config, undo_tracker, pipeline,
+        } = self;
+        let mut dispatcher = Dispatcher {
+            registry, control, bindings, app_config, undo_tracker, pipeline, proxy,
+            held: None,
+            gestures: Recognizer::default(),
+        };
+
+        match source {
+            Source::EventLoop(event_loop) => {
+                // Important: Create the receiver after registering hotkeys
+                let hotkey_channel = GlobalHotKeyEvent::receiver();
+
+                event_loop.set_control_flow(ControlFlow::Wait);
+
+                event_loop.run(move |event, elwt| {
+                    if let Event::UserEvent(event) = event {
+                        if dispatcher.handle_event(event) {
+                            elwt.exit();
+                            return;
+                        }
+                    }
+
+                    while let Ok(event) = hotkey_channel.try_recv() {
+                        match event.state() {
+                            HotKeyState::Pressed => dispatcher.handle_press(event.id),
+                            HotKeyState::Released => dispatcher.handle_release(event.id),
+                        }
+                    }
+
+                    // Wake up in time for gestures that are decided by waiting
+                    dispatcher.tick();
+                    match dispatcher.deadline() {
+                        Some(deadline) => elwt.set_control_flow(ControlFlow::WaitUntil(deadline)),
+                        None => elwt.set_control_flow(ControlFlow::Wait),
                     }
+                })?;
+            },
+            #[cfg(target_os = "linux")]
+            Source::Evdev(_, receiver) => loop {
+                let input = match dispatcher.deadline() {
+                    Some(deadline) => match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(input) => Some(input),
+                        Err(mpsc::RecvTimeoutError::Timeout) => None,
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                    },
+                    None => match receiver.recv() {
+                        Ok(input) => Some(input),
+                        Err(_) => break,
+                    },
+                };
+
+                let stop = match input {
+                    Some(Input::Event(event)) => dispatcher.handle_event(event),
+                    Some(Input::Pressed(id)) => {
+                        dispatcher.handle_press(id);
+                        false
+                    },
+                    Some(Input::Released(id)) => {
+                        dispatcher.handle_release(id);
+                        false
+                    },
+                    None => false,
+                };
+                if stop {
+                    break;
                 }
-            }
-        })?;
-        
+                dispatcher.tick();
+            },
+        }
+
         Ok(())
     }
 }
+
+/// The listener's state while running, shared by both backends
+struct Dispatcher {
+    registry: Registry,
+    control: Arc<Control>,
+    bindings: Vec<Binding>,
+    app_config: SharedConfig,
+    undo_tracker: Arc<UndoTracker>,
+    pipeline: Pipeline,
+    proxy: ListenerProxy,
+    /// The hold hotkey that started the current recording, which stops it when released
+    held: Option<u32>,
+    gestures: Recognizer,
+}
+
+impl Dispatcher {
+    /// Handles an event from another thread. Returns true when the listener should stop.
+    fn handle_event(&mut self, event: ListenerEvent) -> bool {
+        let registry = &self.registry;
+        match event {
+            ListenerEvent::SetBindings(bindings) => {
+                let recording = self.control.is_recording();
+                if let Err(e) = replace_bindings(registry, &mut self.bindings, &bindings, recording) {
+                    error!("Failed to register hotkeys, keeping the old ones: {:?}", e);
+                }
+            },
+            ListenerEvent::Toggle(profile) => {
+                toggle_recording(registry, &self.control, &mut self.bindings, &profile);
+            },
+            ListenerEvent::Start(profile) if !self.control.is_recording() => {
+                toggle_recording(registry, &self.control, &mut self.bindings, &profile);
+            },
+            ListenerEvent::Stop if self.control.is_recording() => {
+                toggle_recording(registry, &self.control, &mut self.bindings, "");
+            },
+            ListenerEvent::Cancel => {
+                cancel_recording(registry, &self.control, &mut self.bindings);
+            },
+            ListenerEvent::Shutdown => {
+                if self.control.is_recording() {
+                    info!("Finishing the current recording");
+                }
+                self.control.shut_down();
+                unregister_all(registry, &mut self.bindings);
+                return true;
+            },
+            _ => {},
+        }
+        false
+    }
+
+    /// Handles a press of the registered hotkey with the given id
+    fn handle_press(&mut self, id: u32) {
+        let Some(binding) = self.bindings.iter().find(|b| b.registered && b.hotkey.id() == id) else {
+            return;
+        };
+
+        let actions = match &binding.actions {
+            HotkeyActions::Action(action) => vec![action.clone()],
+            HotkeyActions::Gestures(gestures) => {
+                self.gestures.press(id, gestures, &self.app_config.load().0.gestures, Instant::now())
+            },
+        };
+        for action in actions {
+            self.run_action(action, id);
+        }
+    }
+
+    /// Handles the release of a registered hotkey
+    fn handle_release(&mut self, id: u32) {
+        if let Some(action) = self.gestures.release(id, Instant::now()) {
+            self.run_action(action, id);
+        }
+
+        // Letting go of a hold hotkey stops the recording it started
+        if self.held != Some(id) {
+            return;
+        }
+        self.held = None;
+        if self.control.is_recording() {
+            toggle_recording(&self.registry, &self.control, &mut self.bindings, "");
+        }
+    }
+
+    /// Runs the gestures whose time is up
+    fn tick(&mut self) {
+        let config = self.app_config.load();
+        for (id, action) in self.gestures.tick(&config.0.gestures, Instant::now()) {
+            self.run_action(action, id);
+        }
+    }
+
+    /// When `tick` next needs to run, if a gesture is waiting
+    fn deadline(&self) -> Option<Instant> {
+        self.gestures.deadline(&self.app_config.load().0.gestures)
+    }
+
+    /// Runs a hotkey's action. `id` is the hotkey, which a hold waits to be released.
+    fn run_action(&mut self, action: HotkeyAction, id: u32) {
+        match action {
+            HotkeyAction::Toggle(profile) => {
+                let profile = profile.unwrap_or_else(|| self.control.selected_profile());
+                toggle_recording(&self.registry, &self.control, &mut self.bindings, &profile);
+            },
+            HotkeyAction::Hold(profile) => {
+                if !self.control.is_recording() {
+                    let profile = profile.unwrap_or_else(|| self.control.selected_profile());
+                    toggle_recording(&self.registry, &self.control, &mut self.bindings, &profile);
+                    self.held = Some(id);
+                }
+            },
+            HotkeyAction::HandsFree(profile) => {
+                if self.control.is_recording() {
+                    toggle_recording(&self.registry, &self.control, &mut self.bindings, "");
+                } else {
+                    let profile = profile.unwrap_or_else(|| self.control.selected_profile());
+                    if self.control.start_hands_free(&profile) {
+                        info!("Hands-free recording started (profile: {})", profile);
+                        set_cancel_registered(&self.registry, &mut self.bindings, true);
+                    }
+                }
+            },
+            HotkeyAction::Cancel => {
+                cancel_recording(&self.registry, &self.control, &mut self.bindings);
+            },
+            HotkeyAction::Undo => {
+                // Undoing sends key presses and waits, so keep it off the event loop
+                let app_config = self.app_config.load_full();
+                let undo_tracker = self.undo_tracker.clone();
+                let proxy = self.proxy.clone();
+                thread::spawn(move || {
+                    let config = &app_config.0;
+                    match undo_tracker.undo(config) {
+                        Ok(Some(insertion)) if config.undo.redictate => {
+                            let _ = proxy.send_event(ListenerEvent::Start(insertion.profile));
+                        },
+                        Ok(_) => {},
+                        Err(e) => error!("Failed to undo: {:?}", e),
+                    }
+                });
+            },
+            HotkeyAction::Retranscribe => {
+                let app_config = self.app_config.load_full();
+                let profile = app_config.0.retranscribe.profile.clone();
+                if let Err(e) = self.pipeline.retranscribe(profile, app_config) {
+                    error!("Failed to re-transcribe: {:#}", e);
+                }
+            },
+            HotkeyAction::CycleProfile => {
+                let config = self.app_config.load();
+                let mut names: Vec<&str> = vec![DEFAULT_PROFILE];
+                names.extend(config.0.profiles.keys().map(String::as_str));
+                let selected = self.control.selected_profile();
+                let next = names.iter()
+                    .position(|name| *name == selected)
+                    .map_or(0, |index| (index + 1) % names.len());
+                self.control.select_profile(names[next]);
+                info!("Selected profile {}", names[next]);
+            },
+            HotkeyAction::PasteLast => {
+                let Some(transcript) = self.pipeline.last_transcript() else {
+                    info!("Nothing has been transcribed yet");
+                    return;
+                };
+                // Pasting waits for the hotkey to be released, so keep it off the event loop
+                let app_config = self.app_config.load_full();
+                let undo_tracker = self.undo_tracker.clone();
+                thread::spawn(move || match paste::paste_text(&transcript.text, &app_config.0) {
+                    Ok(true) => undo_tracker.record(&transcript.text, &transcript.profile, transcript.recording.as_deref()),
+                    Ok(false) => {},
+                    Err(e) => error!("Failed to paste: {:?}", e),
+                });
+            },
+            HotkeyAction::Pause => match self.control.toggle_pause() {
+                Some(true) => info!("Recording paused"),
+                Some(false) => info!("Recording resumed"),
+                None => debug!("Not recording, nothing to pause"),
+            },
+        }
+    }
+}
+
+// Starts or stops recording. Cancel hotkeys are only registered while recording.
+fn toggle_recording(registry: &Registry, control: &Control, bindings: &mut [Binding], profile: &str) {
+    match control.toggle(profile) {
+        true => {
+            info!("Recording started (profile: {})", profile);
+            set_cancel_registered(registry, bindings, true);
+        },
+        false => {
+            info!("Recording stopped");
+            set_cancel_registered(registry, bindings, false);
+        },
+    }
+}
+
+fn cancel_recording(registry: &Registry, control: &Control, bindings: &mut [Binding]) {
+    if control.cancel() {
+        info!("Recording cancelled");
+    }
+    set_cancel_registered(registry, bindings, false);
+}
+
+// Cancel hotkeys are only taken while recording
+fn is_cancel(binding: &Binding) -> bool {
+    binding.actions == HotkeyActions::Action(HotkeyAction::Cancel)
+}
+
+fn set_cancel_registered(registry: &Registry, bindings: &mut [Binding], registered: bool) {
+    for binding in bindings.iter_mut().filter(|b| is_cancel(b)) {
+        match registered {
+            true => {
+                if let Err(e) = binding.register(registry) {
+                    warn!("Failed to register hotkey {}: {:?}", binding.key, e);
+                }
+            },
+            false => binding.unregister(registry),
+        }
+    }
+}
+
+// Parses the hotkeys and checks that no key combination is bound twice. The same
+// binding set twice is only warned about, two different actions are an error.
+fn parse_bindings(config: &[HotkeyBinding]) -> Result<Vec<Binding>> {
+    let mut bindings: Vec<Binding> = Vec::new();
+
+    for binding in config {
+        let hotkey = HotKey::from_str(&binding.hotkey)
+            .map_err(anyhow::Error::from)
+            .context(format!("Invalid hotkey '{}' for {}", binding.hotkey, binding.key))?;
+
+        match bindings.iter().find(|b| b.hotkey == hotkey) {
+            Some(other) if other.actions == binding.actions => {
+                warn!("Hotkey '{}' for {} is also set by {}, ignoring it", binding.hotkey, binding.key, other.key);
+            },
+            Some(other) => bail!(
+                "Hotkey '{}' is bound twice: to {} by {} and to {} by {}",
+                binding.hotkey, other.actions, other.key, binding.actions, binding.key
+            ),
+            None => bindings.push(Binding {
+                hotkey,
+                hotkey_string: binding.hotkey.clone(),
+                actions: binding.actions.clone(),
+                key: binding.key.clone(),
+                registered: false,
+            }),
+        }
+    }
+
+    Ok(bindings)
+}
+
+// Registers every hotkey, except cancel hotkeys when not recording. If one fails, the
+// ones already registered are unregistered again.
+fn register_all(registry: &Registry, bindings: &mut [Binding], recording: bool) -> Result<()> {
+    for index in 0..bindings.len() {
+        let binding = &mut bindings[index];
+        if is_cancel(binding) && !recording {
+            continue;
+        }
+        match binding.register(registry) {
+            Ok(()) => debug!("Registered hotkey {} to {} ({})", binding.hotkey_string, binding.actions, binding.key),
+            Err(e) => {
+                let message = format!("Failed to register hotkey '{}' for {}", binding.hotkey_string, binding.key);
+                unregister_all(registry, bindings);
+                return Err(e.context(message));
+            },
+        }
+    }
+    Ok(())
+}
+
+fn unregister_all(registry: &Registry, bindings: &mut [Binding]) {
+    for binding in bindings {
+        binding.unregister(registry);
+    }
+}
+
+// Swaps the registered hotkeys for new ones. If the new ones can't all be registered,
+// the current ones are registered again.
+fn replace_bindings(registry: &Registry, bindings: &mut Vec<Binding>, config: &[HotkeyBinding], recording: bool) -> Result<()> {
+    let mut new_bindings = parse_bindings(config)?;
+    unregister_all(registry, bindings);
+
+    match register_all(registry, &mut new_bindings, recording) {
+        Ok(()) => {
+            *bindings = new_bindings;
+            info!("Hotkeys updated");
+            Ok(())
+        },
+        Err(e) => {
+            if let Err(e) = register_all(registry, bindings, recording) {
+                error!("Failed to register the old hotkeys again: {:?}", e);
+            }
+            Err(e)
+        },
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::Path;
+
+/// Held for as long as Talky runs. The lock is released by the OS when the process
+/// exits, however it exits, so a stale lock file never blocks a new start.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Takes the lock, or fails with a message naming the running Talky's process ID
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .context(format!("Failed to open lock file: {}", path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {},
+            Err(TryLockError::WouldBlock) => {
+                let pid = fs::read_to_string(path).unwrap_or_default();
+                let pid = match pid.trim() {
+                    "" => String::new(),
+                    pid => format!(" (process {})", pid),
+                };
+                bail!("Talky is already running{}. Use `talky ctl` to control it, or stop it before starting another one.", pid);
+            },
+            Err(TryLockError::Error(e)) => {
+                return Err(e).context(format!("Failed to lock {}", path.display()));
+            },
+        }
+
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+
+        Ok(Self { _file: file })
+    }
+}
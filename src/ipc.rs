@@ -0,0 +1,207 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use crate::config::{Overrides, SharedConfig};
+use crate::clipboard;
+use crate::control::Control;
+use crate::history::History;
+use crate::hotkeys::{ListenerEvent, ListenerProxy};
+use crate::paths::Paths;
+use crate::pipeline::Pipeline;
+use crate::reload;
+use crate::rules::{self, SharedRules};
+use log::*;
+
+// The protocol is one command per connection: the client sends a line such as
+// "toggle work" and the server answers "ok" or "error: <message>", followed by any
+// output lines, then closes the connection.
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the control socket needs to drive the running Talky
+pub struct Server {
+    pub paths: Paths,
+    pub overrides: Overrides,
+    pub control: Arc<Control>,
+    pub app_config: SharedConfig,
+    pub rules: SharedRules,
+    pub listener: ListenerProxy,
+    pub pipeline: Pipeline,
+}
+
+impl Server {
+    /// Listens on the control socket in a background thread. A socket left behind by a
+    /// Talky that is no longer running is replaced.
+    pub fn start(self) -> Result<()> {
+        let path = self.paths.socket_file();
+        if path.exists() {
+            if is_running(&path) {
+                bail!("Another Talky is already listening on {}", path.display());
+            }
+            fs::remove_file(&path)
+                .context(format!("Failed to remove stale socket: {}", path.display()))?;
+        }
+
+        let listener = UnixListener::bind(&path)
+            .context(format!("Failed to create control socket: {}", path.display()))?;
+        // Only the user may drive their dictation
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        debug!("Listening for commands on {}", path.display());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = self.handle(stream) {
+                            warn!("Control socket error: {:#}", e);
+                        }
+                    },
+                    Err(e) => warn!("Control socket error: {:?}", e),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn handle(&self, stream: UnixStream) -> Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let line = line.trim();
+        debug!("Control command: {}", line);
+
+        let reply = match self.run(line) {
+            Ok(output) => format!("ok\n{}", output),
+            Err(e) => format!("error: {:#}\n", e),
+        };
+        (&stream).write_all(reply.as_bytes())?;
+        Ok(())
+    }
+
+    // Runs a command and returns its output
+    fn run(&self, line: &str) -> Result<String> {
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, Some(argument.trim())),
+            None => (line, None),
+        };
+
+        let output = match (command, argument) {
+            ("start", profile) => {
+                if self.control.is_recording() {
+                    return Ok("Already recording\n".to_string());
+                }
+                self.send(ListenerEvent::Start(self.profile(profile)?))?;
+                String::new()
+            },
+            ("stop", None) => {
+                if !self.control.is_recording() {
+                    return Ok("Not recording\n".to_string());
+                }
+                self.send(ListenerEvent::Stop)?;
+                String::new()
+            },
+            ("toggle", profile) => {
+                self.send(ListenerEvent::Toggle(self.profile(profile)?))?;
+                String::new()
+            },
+            ("cancel", None) => {
+                if !self.control.is_recording() {
+                    return Ok("Not recording\n".to_string());
+                }
+                self.send(ListenerEvent::Cancel)?;
+                String::new()
+            },
+            ("status", None) => {
+                let state = if self.control.is_recording() { "recording" } else { "idle" };
+                let mut output = format!("state: {}\n", state);
+                if self.control.is_recording() {
+                    output.push_str(&format!("profile: {}\n", self.control.profile()));
+                }
+                output.push_str(&format!("selected profile: {}\n", self.control.selected_profile()));
+                output
+            },
+            ("switch-profile", Some(profile)) => {
+                let profile = self.profile(Some(profile))?;
+                self.control.select_profile(&profile);
+                info!("Selected profile {}", profile);
+                String::new()
+            },
+            ("reload", None) => {
+                reload::reload(&self.paths, &self.overrides, &self.app_config, &self.listener)?;
+                info!("Reloaded config from {}", self.paths.config_file.display());
+                rules::reload(&self.paths.rules_file, &self.rules)?;
+                info!("Reloaded rules from {}", self.paths.rules_file.display());
+                String::new()
+            },
+            ("retranscribe", profile) => {
+                let app_config = self.app_config.load_full();
+                let profile = match profile {
+                    Some(profile) => self.profile(Some(profile))?,
+                    None => app_config.0.retranscribe.profile.clone(),
+                };
+                self.pipeline.retranscribe(profile, app_config)?;
+                String::new()
+            },
+            ("copy", id) => {
+                // The running Talky owns the clipboard, so on X11 the text stays there
+                // after `talky history copy` exits
+                let id = id.map(|id| id.parse().context(format!("Invalid id: {}", id))).transpose()?;
+                let entry = History::new(self.paths.history_file()).find(id)?;
+                clipboard::copy_to_clipboard(&entry.text)?;
+                format!("Copied transcription {} to the clipboard\n", entry.id)
+            },
+            _ => bail!("Unknown command: {}", line),
+        };
+
+        Ok(output)
+    }
+
+    // The named profile if it exists, or the selected one
+    fn profile(&self, name: Option<&str>) -> Result<String> {
+        match name {
+            Some(name) if self.app_config.load().0.profile(name).is_none() => {
+                bail!("Profile '{}' not found", name)
+            },
+            Some(name) => Ok(name.to_string()),
+            None => Ok(self.control.selected_profile()),
+        }
+    }
+
+    fn send(&self, event: ListenerEvent) -> Result<()> {
+        self.listener.send_event(event)
+    }
+}
+
+/// Whether a Talky is listening on the control socket
+pub fn is_running(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+}
+
+/// Sends a command to the running Talky and returns its output
+pub fn send(path: &Path, command: &str) -> Result<String> {
+    let mut stream = UnixStream::connect(path)
+        .context(format!("Talky doesn't seem to be running (no control socket at {})", path.display()))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+
+    writeln!(stream, "{}", command)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+
+    match reply.split_once('\n') {
+        Some(("ok", output)) => Ok(output.to_string()),
+        Some((error, _)) => Err(anyhow!(error.strip_prefix("error: ").unwrap_or(error).to_string())),
+        None => bail!("No reply from Talky"),
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::Result;
+use enigo::{Direction, Enigo, Key, Keyboard};
+use std::fmt;
+use std::str::FromStr;
+
+/// A key combination sent with synthetic key presses, such as "ctrl+shift+v".
+/// Modifiers come first and the last part is the key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chord {
+    modifiers: Vec<Key>,
+    key: Key,
+    text: String,
+}
+
+impl Chord {
+    /// Ctrl+V, or Cmd+V on macOS
+    pub fn paste() -> Self {
+        #[cfg(target_os = "macos")]
+        let chord = "cmd+v";
+        #[cfg(not(target_os = "macos"))]
+        let chord = "ctrl+v";
+        chord.parse().expect("valid chord")
+    }
+
+    /// Presses the modifiers, clicks the key and releases the modifiers again
+    pub fn send(&self, enigo: &mut Enigo) -> Result<()> {
+        for modifier in &self.modifiers {
+            enigo.key(*modifier, Direction::Press)?;
+        }
+
+        let result = enigo.key(self.key, Direction::Click);
+
+        // Always release the modifiers, even if the key failed, so none are left stuck
+        for modifier in self.modifiers.iter().rev() {
+            enigo.key(*modifier, Direction::Release)?;
+        }
+
+        Ok(result?)
+    }
+}
+
+impl FromStr for Chord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<String> = s.split('+').map(|p| p.trim().to_lowercase()).collect();
+        let Some((key, modifiers)) = parts.split_last() else {
+            return Err(anyhow::anyhow!("empty key combination"));
+        };
+
+        let modifiers = modifiers.iter()
+            .map(|m| parse_modifier(m).ok_or_else(|| anyhow::anyhow!("unknown modifier '{}'", m)))
+            .collect::<Result<Vec<_>>>()?;
+        let key = parse_key(key).ok_or_else(|| anyhow::anyhow!("unknown key '{}'", key))?;
+
+        Ok(Self { modifiers, key, text: s.to_string() })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn parse_modifier(name: &str) -> Option<Key> {
+    match name {
+        "ctrl" | "control" => Some(Key::Control),
+        "shift" => Some(Key::Shift),
+        "alt" | "option" => Some(Key::Alt),
+        "super" | "meta" | "cmd" | "command" | "win" => Some(Key::Meta),
+        _ => None,
+    }
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(Key::Unicode(c));
+    }
+
+    match name {
+        #[cfg(not(target_os = "macos"))]
+        "insert" => Some(Key::Insert),
+        "enter" | "return" => Some(Key::Return),
+        "tab" => Some(Key::Tab),
+        "space" => Some(Key::Space),
+        "backspace" => Some(Key::Backspace),
+        "delete" => Some(Key::Delete),
+        "escape" | "esc" => Some(Key::Escape),
+        "home" => Some(Key::Home),
+        "end" => Some(Key::End),
+        "up" => Some(Key::UpArrow),
+        "down" => Some(Key::DownArrow),
+        "left" => Some(Key::LeftArrow),
+        "right" => Some(Key::RightArrow),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_modifiers_and_key() {
+        let chord: Chord = "Ctrl + Shift+V".parse().unwrap();
+        assert_eq!(chord.modifiers, vec![Key::Control, Key::Shift]);
+        assert_eq!(chord.key, Key::Unicode('v'));
+        assert_eq!(chord.to_string(), "Ctrl + Shift+V");
+    }
+
+    #[test]
+    fn named_keys_and_aliases() {
+        let chord: Chord = "cmd+option+return".parse().unwrap();
+        assert_eq!(chord.modifiers, vec![Key::Meta, Key::Alt]);
+        assert_eq!(chord.key, Key::Return);
+        assert_eq!("esc".parse::<Chord>().unwrap().key, Key::Escape);
+    }
+
+    #[test]
+    fn unknown_parts_are_errors() {
+        assert!("hyper+v".parse::<Chord>().unwrap_err().to_string().contains("unknown modifier 'hyper'"));
+        assert!("ctrl+pgup".parse::<Chord>().unwrap_err().to_string().contains("unknown key 'pgup'"));
+        assert!("ctrl+".parse::<Chord>().is_err());
+        assert!("".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn paste_is_a_valid_chord() {
+        assert_eq!(Chord::paste().modifiers.len(), 1);
+    }
+}
@@ -1,100 +1,143 @@
 mod audio;
 mod hotkeys;
+mod gestures;
 mod speech;
 mod clipboard;
 mod config;
+mod validate;
+mod paths;
+mod cli;
+mod commands;
+mod reload;
+mod control;
+mod postprocess;
+mod spoken;
+mod rules;
+mod typing;
+mod keys;
+mod paste;
+mod x11;
+mod output;
+mod pipeline;
+mod undo;
+mod recordings;
+mod history;
+mod events;
+mod app;
+mod instance;
+mod cues;
+mod tui;
+#[cfg(target_os = "linux")]
+mod evdev_keys;
+#[cfg(unix)]
+mod ipc;
+#[cfg(target_os = "linux")]
+mod dbus;
+#[cfg(target_os = "linux")]
+mod notifications;
 
-use std::sync::Arc;
-use std::thread;
-use std::path::Path;
 use anyhow::Result;
-use hotkeys::HotkeyListener;
-use config::{Config, ApiKeyConfig};
-use std::fs;
+use app::App;
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand, GlobalArgs};
+use config::{Config, ApiKeyConfig, Overrides};
+use paths::Paths;
 use log::*;
 
 
 fn main() -> Result<()> { 
-    // TODO stderrlog can get verbosity from args, either do that or from config or whatever
-    stderrlog::new().module(module_path!()).verbosity(log::Level::Info).init().unwrap();
+    let cli = Cli::parse();
+
+    // The TUI shows log lines itself, they would mess up the screen on stderr
+    let tui_logs = matches!(cli.command, Some(Command::Tui)).then(tui::LogBuffer::default);
+    init_logging(&cli.global, tui_logs.clone())?;
+
+    let paths = Paths::resolve(cli.global.config.as_deref())?;
+    paths.create_dirs()?;
+
+    let overrides = Overrides::new(&cli.global.set)?;
+
+    let command = cli.command.unwrap_or(Command::Run);
+    // Only starting Talky picks up files left in the current directory by older versions,
+    // other commands may well be run from some unrelated directory
+    if cli.global.config.is_none() && matches!(command, Command::Run | Command::Tui) {
+        paths.migrate_from_cwd()?;
+    }
+
+    match command {
+        Command::Run => App::start(&paths, &overrides)?.run(),
+        Command::Tui => tui::run(App::start(&paths, &overrides)?, tui_logs.unwrap_or_default()),
+        Command::Transcribe { file, profile } => commands::transcribe(&paths, &overrides, &file, &profile),
+        Command::Retranscribe { profile, nth } => commands::retranscribe(&paths, &overrides, &profile, nth),
+        Command::History(command) => commands::history(&paths, command),
+        Command::Ctl(command) => commands::ctl(&paths, command),
+        Command::Devices => commands::devices(),
+        Command::Config(ConfigCommand::Init { force }) => commands::config_init(&paths, force),
+        Command::Config(ConfigCommand::Check) => commands::config_check(&paths, &overrides),
+        Command::Config(ConfigCommand::Show) => commands::config_show(&paths, &overrides),
+        Command::Doctor => commands::doctor(&paths, &overrides),
+    }
+}
+
+// Sets up logging to stderr, or the TUI's buffer, and optionally a log file, at the
+// level given by -v/-q
+fn init_logging(args: &GlobalArgs, tui_logs: Option<tui::LogBuffer>) -> Result<()> {
+    let mut dispatch = fern::Dispatch::new()
+        .level(log::LevelFilter::Off)
+        .level_for(module_path!(), args.log_level());
+
+    dispatch = match tui_logs {
+        Some(buffer) => dispatch.chain(tui::log_output(buffer)),
+        None => dispatch.chain(
+            fern::Dispatch::new()
+                .format(|out, message, record| out.finish(format_args!("{} {}", record.level(), message)))
+                .chain(std::io::stderr())
+        ),
+    };
+
+    if let Some(log_file) = &args.log_file {
+        let file = fern::log_file(log_file)
+            .map_err(|e| anyhow::anyhow!("Failed to open log file {}: {}", log_file.display(), e))?;
+        dispatch = dispatch.chain(
+            fern::Dispatch::new()
+                .format(|out, message, record| out.finish(format_args!(
+                    "{} {} {}",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    record.level(),
+                    message
+                )))
+                .chain(file)
+        );
+    }
+
+    dispatch.apply()?;
+    Ok(())
+}
+
+/// Loads and validates the config and API key. Validation problems are logged, and
+/// errors stop the load.
+pub fn load_config(paths: &Paths, overrides: &Overrides) -> Result<(Config, ApiKeyConfig)> {
+    let config_path = paths.config_file.as_path();
 
     // Load configuration
-    let config_path = Path::new("config.yaml");
     debug!("Loading configuration from: {}", config_path.display());
-    let config = Config::load(config_path)?;
-    let file_path = "recording";
+    let config = Config::load(config_path, overrides)?;
+
+    let report = validate::check(config_path, &config, overrides)?;
+    report.log();
+    if report.has_errors() {
+        return Err(anyhow::anyhow!("{} has {} error(s), see above", config_path.display(), report.error_count()));
+    }
 
-    cleanup_wav_files(file_path)?;
-    
     // Load API key
-    let api_key_path = Path::new("apikey.yaml");
+    let api_key_path = paths.api_key_file.as_path();
     debug!("Loading API key from: {}", api_key_path.display());
     let api_key = ApiKeyConfig::load(api_key_path)?;
     
     debug!("API URL: {}", config.api.url);
     if api_key.key == "YOUR_API_KEY_HERE" {
-        warn!("Warning: Using placeholder API key. Please edit apikey.yaml with your actual key, unless you are using a local model.");
-    }
-    
-    info!("Press Ctrl+Shift+Space to start/stop recording");
-    
-    // Initialize hotkey listener
-    let mut hotkey_listener = HotkeyListener::new()?;
-    hotkey_listener.setup_hotkey(&config.api.hotkey)?;
-    
-    // Get shared recording state
-    let recording = hotkey_listener.get_recording_state();
-    
-    // Create shared configuration and API key
-    let app_config = Arc::new((config, api_key));
-    
-    // Start recording management in a separate thread
-    let recording_clone = recording.clone();
-    let app_config_clone = app_config.clone();
-    let recording_thread = thread::spawn(move || -> Result<()> {
-        
-        debug!("Starting audio thread with recording flag initially: {}", 
-                 recording_clone.load(std::sync::atomic::Ordering::SeqCst));
-        
-        // Start the audio recording system
-        // It will internally monitor the recording state flag
-        audio::record_audio(file_path, recording_clone, app_config_clone)
-    });
-    
-    debug!("Starting hotkey listener...");
-    hotkey_listener.run()?;
-    
-    // Wait for recording thread to finish TODO this is never reached because we block the main thread
-    if let Err(e) = recording_thread.join().unwrap() {
-        error!("Recording error: {:?}", e);
+        warn!("Warning: Using placeholder API key. Please edit {} with your actual key, unless you are using a local model.", api_key_path.display());
     }
-    
-    Ok(())
-}
 
-// Deletes wav files with the given prefix in the current directory
-// TODO seems dangerous? maybe pick a prefix that is unlikely to match other files
-fn cleanup_wav_files(file_prefix: &str) -> Result<()> {
-    let current_dir = std::env::current_dir()?;
-    let entries = fs::read_dir(&current_dir)?;
-    
-    for entry in entries {
-        let entry = entry?;
-        let path = entry.path();
-        
-        // Check if it's a file
-        if path.is_file() {
-            // Check if it's a .wav file with the given prefix
-            if let Some(file_name) = path.file_name() {
-                let file_name_str = file_name.to_string_lossy();
-                if file_name_str.starts_with(file_prefix) && file_name_str.ends_with(".wav") {
-                    debug!("Removing file: {}", path.display());
-                    fs::remove_file(path)?;
-                }
-            }
-        }
-    }
-
-    Ok(())
-    
-}
\ No newline at end of file
+    Ok((config, api_key))
+}
@@ -0,0 +1,93 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+use zbus::blocking::Connection;
+use zbus::zvariant::Value;
+use crate::config::SharedConfig;
+use crate::events::AppEvent;
+use log::*;
+
+const ICON: &str = "audio-input-microphone";
+
+/// How long the recording started/stopped notifications stay up
+const RECORDING_TIMEOUT_MS: i32 = 2000;
+
+/// Shows freedesktop notifications for app events, in a background thread. Which ones
+/// are shown is read from the current config for every event.
+pub fn show(app_config: SharedConfig, events: mpsc::Receiver<AppEvent>) -> Result<()> {
+    let connection = Connection::session()?;
+
+    thread::spawn(move || {
+        // Started/stopped replace each other instead of piling up
+        let mut recording_id = 0;
+
+        for event in events {
+            let config = app_config.load();
+            let notifications = &config.0.notifications;
+
+            let result = match event {
+                AppEvent::RecordingStarted { profile } if notifications.recording => {
+                    let body = format!("Profile: {}", profile);
+                    notify(&connection, recording_id, "Recording", &body, RECORDING_TIMEOUT_MS)
+                        .map(|id| recording_id = id)
+                },
+                AppEvent::RecordingStopped if notifications.recording => {
+                    notify(&connection, recording_id, "Recording stopped", "Transcribing…", RECORDING_TIMEOUT_MS)
+                        .map(|id| recording_id = id)
+                },
+                AppEvent::RecordingCancelled if notifications.recording || notifications.cancelled => {
+                    notify(&connection, recording_id, "Recording cancelled", "The audio was thrown away", RECORDING_TIMEOUT_MS)
+                        .map(|id| recording_id = id)
+                },
+                AppEvent::TranscriptionFailed { unreachable: true, recording, .. } if notifications.unreachable => {
+                    // Only the last few recordings are kept, and none with recordings.keep 0
+                    let (summary, body) = match recording {
+                        Some(_) => ("Transcription postponed", format!(
+                            "The speech-to-text API couldn't be reached. The recording is kept until {} newer ones \
+                                replace it, run `talky retranscribe` once the API is back.",
+                            config.0.recordings.keep
+                        )),
+                        None => ("Transcription failed", "The speech-to-text API couldn't be reached, and the recording \
+                            wasn't kept.".to_string()),
+                    };
+                    notify(&connection, 0, summary, &body, -1).map(|_| ())
+                },
+                AppEvent::TranscriptionFailed { error, unreachable: false, .. } if notifications.failed => {
+                    notify(&connection, 0, "Transcription failed", &error, -1).map(|_| ())
+                },
+                AppEvent::Error { message } if notifications.failed => {
+                    notify(&connection, 0, "Talky error", &message, -1).map(|_| ())
+                },
+                AppEvent::Transcribed { text, confidence: Some(confidence), .. }
+                    if notifications.low_confidence && confidence < notifications.low_confidence_threshold =>
+                {
+                    let summary = format!("Low confidence transcription ({:.0}%)", confidence * 100.0);
+                    notify(&connection, 0, &summary, &text, -1).map(|_| ())
+                },
+                _ => Ok(()),
+            };
+
+            if let Err(e) = result {
+                warn!("Failed to show notification: {:?}", e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// Sends a notification, replacing the one with `replaces_id` if it isn't 0, and
+// returns its id. A timeout of -1 leaves it to the notification server.
+fn notify(connection: &Connection, replaces_id: u32, summary: &str, body: &str, timeout_ms: i32) -> Result<u32> {
+    let actions: Vec<&str> = Vec::new();
+    let hints: HashMap<&str, Value> = HashMap::new();
+    let reply = connection.call_method(
+        Some("org.freedesktop.Notifications"),
+        "/org/freedesktop/Notifications",
+        Some("org.freedesktop.Notifications"),
+        "Notify",
+        &("Talky", replaces_id, ICON, summary, body, actions, hints, timeout_ms),
+    )?;
+    Ok(reply.body().deserialize()?)
+}
@@ -0,0 +1,305 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{mpsc, OnceLock};
+use std::thread;
+use std::time::Duration;
+use crate::config::{Config, SinkConfig};
+use crate::events::{AppEvent, Events};
+use crate::undo::UndoTracker;
+use crate::{clipboard, paste, typing};
+use log::*;
+
+/// A finished transcription on its way to the sinks
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    /// Post-processed text, as it would be pasted
+    pub text: String,
+    pub profile: String,
+    pub time: DateTime<Local>,
+    /// Cached recording it was transcribed from, if the recording was kept
+    pub recording: Option<PathBuf>,
+}
+
+impl Transcript {
+    pub fn new(text: String, profile: &str, recording: Option<PathBuf>) -> Self {
+        Self {
+            text,
+            profile: profile.to_string(),
+            time: Local::now(),
+            recording,
+        }
+    }
+
+    /// One line of JSON, used by the stdout, webhook, socket and FIFO sinks
+    fn to_json(&self) -> String {
+        serde_json::json!({
+            "time": self.time.to_rfc3339(),
+            "profile": self.profile,
+            "text": self.text.trim(),
+        }).to_string()
+    }
+}
+
+/// Somewhere a transcription can be sent
+pub trait Output {
+    /// Short description for log messages, e.g. "file /tmp/dictation.log"
+    fn name(&self) -> String;
+
+    fn send(&self, transcript: &Transcript) -> Result<()>;
+}
+
+/// Pastes into the focused app, using the strategy for that app
+struct PasteOutput<'a> {
+    config: &'a Config,
+    undo: &'a UndoTracker,
+}
+
+impl Output for PasteOutput<'_> {
+    fn name(&self) -> String {
+        "paste".to_string()
+    }
+
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        if paste::paste_text(&transcript.text, self.config)? {
+            self.undo.record(&transcript.text, &transcript.profile, transcript.recording.as_deref());
+        }
+        Ok(())
+    }
+}
+
+/// Types into the focused app
+struct TypeOutput<'a> {
+    config: &'a Config,
+    undo: &'a UndoTracker,
+}
+
+impl Output for TypeOutput<'_> {
+    fn name(&self) -> String {
+        "type".to_string()
+    }
+
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        typing::type_text(&transcript.text, &self.config.typing)?;
+        self.undo.record(&transcript.text, &transcript.profile, transcript.recording.as_deref());
+        Ok(())
+    }
+}
+
+struct ClipboardOutput;
+
+impl Output for ClipboardOutput {
+    fn name(&self) -> String {
+        "clipboard".to_string()
+    }
+
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        clipboard::copy_to_clipboard(&transcript.text)
+    }
+}
+
+/// Appends a line per transcription to a file
+struct FileOutput {
+    path: PathBuf,
+    template: String,
+    time_format: String,
+}
+
+impl Output for FileOutput {
+    fn name(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        let line = self.template
+            .replace("{time}", &transcript.time.format(&self.time_format).to_string())
+            .replace("{profile}", &transcript.profile)
+            .replace("{text}", transcript.text.trim());
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open {}", self.path.display()))?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+/// Prints JSON lines, for piping talky into other programs
+struct StdoutOutput;
+
+impl Output for StdoutOutput {
+    fn name(&self) -> String {
+        "stdout".to_string()
+    }
+
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", transcript.to_json())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// POSTs the JSON to a URL
+struct WebhookOutput {
+    url: String,
+    timeout: Duration,
+}
+
+impl Output for WebhookOutput {
+    fn name(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .build()?;
+
+        client.post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(transcript.to_json())
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Connects to a Unix socket and writes the JSON line
+struct SocketOutput {
+    path: PathBuf,
+}
+
+impl Output for SocketOutput {
+    fn name(&self) -> String {
+        format!("socket {}", self.path.display())
+    }
+
+    #[cfg(unix)]
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        use std::os::unix::net::UnixStream;
+
+        let mut stream = UnixStream::connect(&self.path)
+            .context(format!("Failed to connect to {}", self.path.display()))?;
+        stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+        writeln!(stream, "{}", transcript.to_json())?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _transcript: &Transcript) -> Result<()> {
+        Err(anyhow::anyhow!("Unix sockets aren't supported on this platform"))
+    }
+}
+
+/// Writes the JSON line to a named pipe
+struct FifoOutput {
+    path: PathBuf,
+}
+
+impl Output for FifoOutput {
+    fn name(&self) -> String {
+        format!("fifo {}", self.path.display())
+    }
+
+    #[cfg(unix)]
+    fn send(&self, transcript: &Transcript) -> Result<()> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        // Opening a FIFO for writing blocks until there's a reader, so don't wait for one
+        let mut fifo = match OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open(&self.path) {
+            Ok(fifo) => fifo,
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => {
+                return Err(anyhow::anyhow!("Nothing is reading from {}", self.path.display()));
+            },
+            Err(e) => return Err(e).context(format!("Failed to open {}", self.path.display())),
+        };
+        writeln!(fifo, "{}", transcript.to_json())?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn send(&self, _transcript: &Transcript) -> Result<()> {
+        Err(anyhow::anyhow!("FIFOs aren't supported on this platform"))
+    }
+}
+
+/// An output for a sink setting. Remote outputs only need the transcript, so they can
+/// be sent from another thread.
+enum Sink<'a> {
+    Local(Box<dyn Output + 'a>),
+    Remote(Box<dyn Output + Send>),
+}
+
+/// Creates the output for a sink setting. Text pasted or typed into an app is
+/// recorded in `undo`.
+fn output<'a>(sink: &SinkConfig, config: &'a Config, undo: &'a UndoTracker) -> Sink<'a> {
+    match sink {
+        SinkConfig::Paste => Sink::Local(Box::new(PasteOutput { config, undo })),
+        SinkConfig::Type => Sink::Local(Box::new(TypeOutput { config, undo })),
+        SinkConfig::Clipboard => Sink::Local(Box::new(ClipboardOutput)),
+        SinkConfig::File { path, template, time_format } => Sink::Local(Box::new(FileOutput {
+            path: path.clone(),
+            template: template.clone(),
+            time_format: time_format.clone(),
+        })),
+        SinkConfig::Stdout => Sink::Local(Box::new(StdoutOutput)),
+        SinkConfig::Webhook { url, timeout_ms } => Sink::Remote(Box::new(WebhookOutput {
+            url: url.clone(),
+            timeout: Duration::from_millis(*timeout_ms),
+        })),
+        SinkConfig::Socket { path } => Sink::Remote(Box::new(SocketOutput { path: path.clone() })),
+        SinkConfig::Fifo { path } => Sink::Local(Box::new(FifoOutput { path: path.clone() })),
+    }
+}
+
+type RemoteBatch = (Transcript, Vec<Box<dyn Output + Send>>, Events);
+
+/// Sends to remote outputs one transcript at a time, in the order they were delivered
+static REMOTE: OnceLock<mpsc::Sender<RemoteBatch>> = OnceLock::new();
+
+fn remote_sender() -> &'static mpsc::Sender<RemoteBatch> {
+    REMOTE.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<RemoteBatch>();
+        thread::Builder::new()
+            .name("remote-sinks".to_string())
+            .spawn(move || {
+                for (transcript, outputs, events) in receiver {
+                    for output in outputs {
+                        send(&*output, &transcript, &events);
+                    }
+                }
+            })
+            .expect("failed to spawn remote sink thread");
+        sender
+    })
+}
+
+fn send(output: &dyn Output, transcript: &Transcript, events: &Events) {
+    if let Err(e) = output.send(transcript) {
+        error!("Failed to output text to {}: {:?}", output.name(), e);
+        events.emit(AppEvent::Error { message: format!("Failed to output text to {}: {:#}", output.name(), e) });
+    }
+}
+
+/// Sends a transcript to each sink. Local sinks run one after the other, in order.
+/// Webhooks and sockets are sent afterwards from a background thread, so a slow
+/// server doesn't hold up pasting. A sink that fails is logged and reported as an error
+/// event, and doesn't stop the rest.
+pub fn deliver(transcript: &Transcript, sinks: &[SinkConfig], config: &Config, undo: &UndoTracker, events: &Events) {
+    let mut remote = Vec::new();
+    for sink in sinks {
+        match output(sink, config, undo) {
+            Sink::Local(output) => send(&*output, transcript, events),
+            Sink::Remote(output) => remote.push(output),
+        }
+    }
+
+    if !remote.is_empty() && remote_sender().send((transcript.clone(), remote, events.clone())).is_err() {
+        error!("Remote sink thread has stopped");
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::Result;
+use enigo::Enigo;
+use std::thread;
+use std::time::Duration;
+use crate::clipboard::{self, ClipboardSnapshot};
+use crate::config::{Config, PasteRule, PasteStrategy};
+use crate::keys::Chord;
+use crate::typing;
+use crate::x11::{self, ActiveWindow};
+use log::*;
+
+/// Terminals that don't paste with Ctrl+V, used after the rules from the config.
+/// Matched against the exact WM_CLASS instance or class.
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    ("gnome-terminal", "ctrl+shift+v"),
+    ("gnome-terminal-server", "ctrl+shift+v"),
+    ("konsole", "ctrl+shift+v"),
+    ("xfce4-terminal", "ctrl+shift+v"),
+    ("mate-terminal", "ctrl+shift+v"),
+    ("lxterminal", "ctrl+shift+v"),
+    ("terminator", "ctrl+shift+v"),
+    ("tilix", "ctrl+shift+v"),
+    ("guake", "ctrl+shift+v"),
+    ("tilda", "ctrl+shift+v"),
+    ("yakuake", "ctrl+shift+v"),
+    ("terminology", "ctrl+shift+v"),
+    ("alacritty", "ctrl+shift+v"),
+    ("kitty", "ctrl+shift+v"),
+    ("foot", "ctrl+shift+v"),
+    ("org.wezfurlong.wezterm", "ctrl+shift+v"),
+    ("com.mitchellh.ghostty", "ctrl+shift+v"),
+    // These paste the primary selection with Shift+Insert
+    ("xterm", "primary"),
+    ("urxvt", "primary"),
+    ("rxvt", "primary"),
+    ("st-256color", "primary"),
+];
+
+/// Returns how to paste into the given window: the first matching config rule,
+/// then the built-in terminal rules, then the platform's paste keys
+pub fn strategy_for(config: &Config, window: Option<&ActiveWindow>) -> PasteStrategy {
+    let Some(window) = window else {
+        return PasteStrategy::Keys(Chord::paste().to_string());
+    };
+
+    if let Some(rule) = config.paste.rules.iter().find(|rule| rule_matches(rule, window)) {
+        return rule.paste.clone();
+    }
+
+    if config.paste.builtin_rules {
+        let builtin = BUILTIN_RULES.iter().find(|(class, _)| {
+            window.class.eq_ignore_ascii_case(class) || window.instance.eq_ignore_ascii_case(class)
+        });
+        if let Some((_, paste)) = builtin {
+            return PasteStrategy::from(paste.to_string());
+        }
+    }
+
+    PasteStrategy::Keys(Chord::paste().to_string())
+}
+
+fn rule_matches(rule: &PasteRule, window: &ActiveWindow) -> bool {
+    if rule.class.is_none() && rule.title.is_none() {
+        return false;
+    }
+
+    let class_matches = rule.class.as_ref().is_none_or(|class| {
+        window.class.eq_ignore_ascii_case(class) || window.instance.eq_ignore_ascii_case(class)
+    });
+    let title_matches = rule.title.as_ref().is_none_or(|title| {
+        window.title.to_lowercase().contains(&title.to_lowercase())
+    });
+
+    class_matches && title_matches
+}
+
+/// Pastes text into the focused app using the strategy for that app. If enabled, the
+/// previous clipboard contents are restored once the app has had time to read the
+/// pasted text. If the clipboard can't be set, the text is typed instead.
+/// Returns false if the text was only copied, so there's nothing in the app to undo.
+pub fn paste_text(text: &str, config: &Config) -> Result<bool> {
+    let window = x11::active_window();
+    let strategy = strategy_for(config, window.as_ref());
+    if let Some(window) = &window {
+        debug!("Pasting into {} ({}) with {:?}", window.class, window.title, strategy);
+    }
+
+    match strategy {
+        PasteStrategy::Keys(keys) => paste_with_keys(text, &keys.parse()?, config)?,
+        PasteStrategy::Type => typing::type_text(text, &config.typing)?,
+        PasteStrategy::Primary => {
+            x11::set_primary(text)?;
+            thread::sleep(Duration::from_millis(config.clipboard.paste_delay_ms));
+            let mut enigo = Enigo::new(&enigo::Settings::default())?;
+            "shift+insert".parse::<Chord>()?.send(&mut enigo)?;
+        },
+        PasteStrategy::ClipboardOnly => {
+            clipboard::copy_to_clipboard(text)?;
+            return Ok(false);
+        },
+    }
+
+    Ok(true)
+}
+
+fn paste_with_keys(text: &str, keys: &Chord, config: &Config) -> Result<()> {
+    let snapshot = if config.clipboard.restore {
+        match ClipboardSnapshot::take() {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                warn!("Clipboard won't be restored: {:?}", e);
+                None
+            },
+        }
+    } else {
+        None
+    };
+
+    if let Err(e) = clipboard::copy_to_clipboard(text) {
+        warn!("Typing the text instead of pasting: {:?}", e);
+        return typing::type_text(text, &config.typing);
+    }
+    clipboard::paste_clipboard(config.clipboard.paste_delay_ms, keys)?;
+
+    if let Some(snapshot) = snapshot {
+        // The paste keystroke is handled asynchronously by the target app
+        thread::sleep(Duration::from_millis(config.clipboard.restore_delay_ms));
+        snapshot.restore(text)?;
+    }
+    
+    Ok(())
+}
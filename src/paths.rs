@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::*;
+use serde::de::DeserializeOwned;
+
+use crate::config::{ApiKeyConfig, Config};
+
+const CONFIG_FILE_NAME: &str = "config.yaml";
+const API_KEY_FILE_NAME: &str = "apikey.yaml";
+const RULES_FILE_NAME: &str = "rules.yaml";
+
+/// Where Talky keeps its files. On Linux these follow the XDG base directory spec
+/// ($XDG_CONFIG_HOME/talky, $XDG_CACHE_HOME/talky), on Mac and Windows the
+/// platform equivalents, plus $XDG_DATA_HOME/talky for the history.
+#[derive(Debug, Clone)]
+pub struct Paths {
+    pub config_file: PathBuf,
+    pub api_key_file: PathBuf,
+    /// Replacement and snippet rules, next to config.yaml. It doesn't have to exist.
+    pub rules_file: PathBuf,
+    pub cache_dir: PathBuf,
+    pub data_dir: PathBuf,
+    /// $XDG_RUNTIME_DIR/talky, or the cache directory where there is no runtime directory
+    pub runtime_dir: PathBuf,
+}
+
+impl Paths {
+    /// Resolves the standard directories. If `config_override` is given, that file is
+    /// used as config.yaml and apikey.yaml and rules.yaml are looked up next to it.
+    pub fn resolve(config_override: Option<&Path>) -> Result<Self> {
+        let dirs = ProjectDirs::from("", "", "talky")
+            .context("Failed to determine the home directory")?;
+
+        let config_file = match config_override {
+            Some(path) => path.to_path_buf(),
+            None => dirs.config_dir().join(CONFIG_FILE_NAME),
+        };
+
+        let config_dir = config_file.parent().unwrap_or(Path::new("."));
+        let api_key_file = config_dir.join(API_KEY_FILE_NAME);
+        let rules_file = config_dir.join(RULES_FILE_NAME);
+
+        Ok(Self {
+            config_file,
+            api_key_file,
+            rules_file,
+            cache_dir: dirs.cache_dir().to_path_buf(),
+            data_dir: dirs.data_dir().to_path_buf(),
+            runtime_dir: dirs.runtime_dir().unwrap_or(dirs.cache_dir()).to_path_buf(),
+        })
+    }
+
+    /// Directory for temporary wav recordings
+    pub fn recordings_dir(&self) -> PathBuf {
+        self.cache_dir.join("recordings")
+    }
+
+    /// Directory for recent recordings kept for re-transcribing
+    pub fn recent_dir(&self) -> PathBuf {
+        self.cache_dir.join("recent")
+    }
+
+    /// Transcription history, one JSON object per line
+    pub fn history_file(&self) -> PathBuf {
+        self.data_dir.join("history.jsonl")
+    }
+
+    /// Unix socket `talky ctl` uses to talk to the running Talky
+    pub fn socket_file(&self) -> PathBuf {
+        self.runtime_dir.join("talky.sock")
+    }
+
+    /// Locked by the running Talky, so a second copy knows it isn't alone
+    pub fn lock_file(&self) -> PathBuf {
+        self.runtime_dir.join("talky.lock")
+    }
+
+    /// Creates the directories we write to
+    pub fn create_dirs(&self) -> Result<()> {
+        let config_dir = self.config_file.parent().unwrap_or(Path::new("."));
+        for dir in [config_dir, &self.recordings_dir(), &self.recent_dir(), &self.data_dir, &self.runtime_dir] {
+            fs::create_dir_all(dir)
+                .context(format!("Failed to create directory: {}", dir.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Older versions kept config.yaml and apikey.yaml in the current directory. If those
+    /// are Talky's files and the standard location doesn't have a config yet, copy them
+    /// over. The originals are left where they are.
+    pub fn migrate_from_cwd(&self) -> Result<()> {
+        if self.config_file.exists() {
+            return Ok(());
+        }
+
+        let current_dir = std::env::current_dir()?;
+        let old_config = current_dir.join(CONFIG_FILE_NAME);
+        if !is_talky_file::<Config>(&old_config) {
+            return Ok(());
+        }
+
+        let old_api_key = current_dir.join(API_KEY_FILE_NAME);
+        let mut files = vec![(old_config, &self.config_file)];
+        if is_talky_file::<ApiKeyConfig>(&old_api_key) && !self.api_key_file.exists() {
+            files.push((old_api_key, &self.api_key_file));
+        }
+
+        for (old, new) in files {
+            fs::copy(&old, new)
+                .context(format!("Failed to copy {} to {}", old.display(), new.display()))?;
+            info!("Copied {} to {}, the old file can be deleted", old.display(), new.display());
+        }
+
+        Ok(())
+    }
+}
+
+// Whether the file exists and parses as one of Talky's config files
+fn is_talky_file<T: DeserializeOwned>(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .is_ok_and(|contents| serde_yaml::from_str::<T>(&contents).is_ok())
+}
@@ -0,0 +1,378 @@
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::Local;
+use crate::config::{AppConfig, Profile};
+use crate::events::{AppEvent, Events};
+use crate::history::{self, Entry, History};
+use crate::output::{self, Transcript};
+use crate::recordings::RecordingCache;
+use crate::rules::SharedRules;
+use crate::speech::Transcription;
+use crate::undo::{self, UndoTracker};
+use crate::{postprocess, speech};
+use log::*;
+
+/// A finished recording waiting to be transcribed
+struct Job {
+    seq: u64,
+    path: PathBuf,
+    profile: String,
+    /// A cached recording transcribed again. It stays in the cache and its text
+    /// replaces the previous transcription.
+    retranscribe: bool,
+    /// Config as it was when the recording stopped
+    app_config: Arc<AppConfig>,
+}
+
+/// A transcription ready to be output
+struct Finished {
+    transcript: Transcript,
+    /// The text as the API returned it, before post-processing
+    raw_text: String,
+    profile: Profile,
+    retranscribe: bool,
+    /// Recorded in the history once the transcript has been output
+    entry: Entry,
+    app_config: Arc<AppConfig>,
+}
+
+enum DeliveryEvent {
+    /// A recording was queued and must be output before the ones after it
+    Queued { seq: u64, deadline: Instant },
+    /// A recording was transcribed. None if transcription failed.
+    Done { seq: u64, finished: Option<Box<Finished>> },
+}
+
+/// Transcribes recordings on a fixed number of worker threads and outputs the results
+/// in the order they were recorded, whatever order they finish in. Clones submit to the
+/// same workers.
+#[derive(Clone)]
+pub struct Pipeline {
+    jobs: mpsc::Sender<Job>,
+    delivery: mpsc::Sender<DeliveryEvent>,
+    next_seq: Arc<AtomicU64>,
+    /// Recordings queued but not yet output, and whether each is a re-transcription
+    pending: Arc<Mutex<BTreeMap<u64, (PathBuf, bool)>>>,
+    /// The last transcript that was output
+    last: Arc<Mutex<Option<Transcript>>>,
+    cache: RecordingCache,
+    events: Events,
+}
+
+impl Pipeline {
+    pub fn start(workers: usize, cache: RecordingCache, undo: Arc<UndoTracker>, history: Arc<History>, rules: SharedRules, events: Events) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (delivery, delivery_receiver) = mpsc::channel();
+
+        // Workers take turns pulling jobs from the shared receiver
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for index in 0..workers.max(1) {
+            let job_receiver = job_receiver.clone();
+            let delivery = delivery.clone();
+            let cache = cache.clone();
+            let events = events.clone();
+            let rules = rules.clone();
+            thread::Builder::new()
+                .name(format!("transcribe-{}", index))
+                .spawn(move || loop {
+                    let job = match job_receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let seq = job.seq;
+                    let finished = transcribe(job, &cache, &rules, &events);
+                    if delivery.send(DeliveryEvent::Done { seq, finished }).is_err() {
+                        break;
+                    }
+                })
+                .expect("failed to spawn transcription worker");
+        }
+
+        let pending = Arc::new(Mutex::new(BTreeMap::new()));
+        let delivery_pending = pending.clone();
+        let delivery_events = events.clone();
+        let last = Arc::new(Mutex::new(None));
+        let delivery_last = last.clone();
+        thread::Builder::new()
+            .name("deliver".to_string())
+            .spawn(move || deliver(delivery_receiver, &undo, &history, &delivery_pending, &delivery_last, &delivery_events))
+            .expect("failed to spawn delivery thread");
+
+        debug!("Started {} transcription worker(s)", workers.max(1));
+
+        Self { jobs, delivery, next_seq: Arc::new(AtomicU64::new(0)), pending, last, cache, events }
+    }
+
+    /// Queues a recording for transcription with the given profile. Once transcribed
+    /// it moves to the recording cache.
+    pub fn submit(&self, path: PathBuf, profile: String, app_config: Arc<AppConfig>) {
+        self.queue(path, profile, false, app_config);
+    }
+
+    /// Queues the latest cached recording to be transcribed again with the given profile
+    pub fn retranscribe(&self, profile: String, app_config: Arc<AppConfig>) -> Result<()> {
+        // The latest recording only reaches the cache once it's transcribed
+        if self.is_transcribing() {
+            bail!("The last recording is still being transcribed, try again when it's done");
+        }
+        let path = self.cache.nth_latest(1)?;
+        info!("Transcribing the last recording again (profile: {})", profile);
+        self.queue(path, profile, true, app_config);
+        Ok(())
+    }
+
+    fn queue(&self, path: PathBuf, profile: String, retranscribe: bool, app_config: Arc<AppConfig>) {
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        self.pending.lock().unwrap().insert(seq, (path.clone(), retranscribe));
+        self.events.emit(AppEvent::Queued { seq, profile: profile.clone() });
+
+        let late_after = Duration::from_millis(app_config.0.transcription.late_after_ms);
+        // The delivery thread must hear about the recording before its result
+        let _ = self.delivery.send(DeliveryEvent::Queued { seq, deadline: Instant::now() + late_after });
+        if self.jobs.send(Job { seq, path, profile, retranscribe, app_config }).is_err() {
+            error!("Transcription workers have stopped");
+        }
+    }
+
+    /// Whether a new recording is queued or being transcribed
+    pub fn is_transcribing(&self) -> bool {
+        self.pending.lock().unwrap().values().any(|(_, retranscribe)| !retranscribe)
+    }
+
+    /// The last transcript that was output, if any
+    pub fn last_transcript(&self) -> Option<Transcript> {
+        self.last.lock().unwrap().clone()
+    }
+
+    /// Waits up to `timeout` for every queued recording to be transcribed and output.
+    /// Returns the new recordings that didn't finish; re-transcriptions are already cached.
+    pub fn wait(&self, timeout: Duration) -> Vec<PathBuf> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let pending = self.pending.lock().unwrap();
+            if pending.is_empty() || Instant::now() >= deadline {
+                return pending.values()
+                    .filter(|(path, retranscribe)| !retranscribe && path.exists())
+                    .map(|(path, _)| path.clone())
+                    .collect();
+            }
+            drop(pending);
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+fn transcribe(job: Job, cache: &RecordingCache, rules: &SharedRules, events: &Events) -> Option<Box<Finished>> {
+    let (config, api_key) = &*job.app_config;
+    let profile = config.profile_or_default(&job.profile);
+
+    let duration_ms = history::wav_duration_ms(&job.path).unwrap_or_else(|e| {
+        warn!("Couldn't read the length of {}: {:?}", job.path.display(), e);
+        0
+    });
+    let started = Instant::now();
+    let result = speech::transcribe_audio(&job.path, &profile.api, api_key, config.notifications.low_confidence);
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    // Keep the last few recordings around for re-transcribing
+    let audio = if job.retranscribe {
+        Some(job.path.clone())
+    } else {
+        cache.store(&job.path, config.recordings.keep).unwrap_or_else(|e| {
+            error!("{:?}", e);
+            None
+        })
+    };
+
+    match result {
+        Ok(Transcription { text, confidence }) => {
+            info!("Transcription ({}, {} ms): {}", profile.name, latency_ms, text);
+            events.emit(AppEvent::Transcribed {
+                seq: job.seq,
+                profile: profile.name.clone(),
+                text: text.clone(),
+                latency_ms,
+                confidence,
+            });
+            // The history keeps the text as it's output
+            let raw_text = text;
+            let text = postprocess::process(&raw_text, &profile, &rules.load());
+            let entry = Entry {
+                id: 0,
+                time: Local::now(),
+                profile: profile.name.clone(),
+                model: profile.api.model.clone(),
+                duration_ms,
+                latency_ms,
+                text: text.clone(),
+                audio: audio.clone().filter(|_| config.history.audio_path),
+            };
+            Some(Box::new(Finished {
+                transcript: Transcript::new(text, &profile.name, audio),
+                raw_text,
+                profile,
+                retranscribe: job.retranscribe,
+                entry,
+                app_config: job.app_config.clone(),
+            }))
+        },
+        Err(e) => {
+            error!("Failed to transcribe audio: {:?}", e);
+            events.emit(AppEvent::TranscriptionFailed {
+                seq: job.seq,
+                error: format!("{:#}", e),
+                unreachable: speech::is_unreachable(&e),
+                recording: audio,
+            });
+            None
+        },
+    }
+}
+
+/// Outputs results in sequence order. If the next result isn't ready by its deadline
+/// it is skipped so later ones aren't held up, and goes to the late sinks when it arrives.
+fn deliver(
+    receiver: mpsc::Receiver<DeliveryEvent>,
+    undo: &UndoTracker,
+    history: &History,
+    pending: &Mutex<BTreeMap<u64, (PathBuf, bool)>>,
+    last: &Mutex<Option<Transcript>>,
+    events: &Events,
+) {
+    let mut deadlines: BTreeMap<u64, Instant> = BTreeMap::new();
+    let mut ready: BTreeMap<u64, Option<Box<Finished>>> = BTreeMap::new();
+    let mut skipped: BTreeSet<u64> = BTreeSet::new();
+    let mut next: u64 = 0;
+
+    loop {
+        // Output everything that's now in order
+        loop {
+            if let Some(finished) = ready.remove(&next) {
+                let deadline = deadlines.remove(&next);
+                if let Some(finished) = finished {
+                    let is_late = deadline.is_some_and(|d| Instant::now() > d);
+                    let outcome = output_result(&finished, is_late, undo, history, last, events);
+                    events.emit(outcome.event(next, &finished));
+                }
+                pending.lock().unwrap().remove(&next);
+                next += 1;
+            } else if deadlines.get(&next).is_some_and(|d| Instant::now() >= *d) {
+                warn!("Transcription {} is taking too long, outputting later ones first", next);
+                deadlines.remove(&next);
+                skipped.insert(next);
+                next += 1;
+            } else {
+                break;
+            }
+        }
+
+        let event = match deadlines.get(&next) {
+            Some(deadline) => match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            },
+            None => match receiver.recv() {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+        };
+
+        match event {
+            DeliveryEvent::Queued { seq, deadline } => {
+                deadlines.insert(seq, deadline);
+            },
+            DeliveryEvent::Done { seq, finished } => {
+                if skipped.remove(&seq) {
+                    if let Some(finished) = finished {
+                        let outcome = output_result(&finished, true, undo, history, last, events);
+                        events.emit(outcome.event(seq, &finished));
+                    }
+                    pending.lock().unwrap().remove(&seq);
+                } else {
+                    ready.insert(seq, finished);
+                }
+            },
+        }
+    }
+}
+
+/// What happened to a transcription
+enum Outcome {
+    /// Output to the sinks, or the late sinks
+    Delivered { late: bool },
+    /// It was an undo phrase, so nothing was output
+    Undone,
+}
+
+impl Outcome {
+    fn event(self, seq: u64, finished: &Finished) -> AppEvent {
+        match self {
+            Outcome::Delivered { late } => AppEvent::Delivered {
+                seq,
+                late,
+                profile: finished.transcript.profile.clone(),
+                text: finished.transcript.text.clone(),
+            },
+            Outcome::Undone => AppEvent::Undone { seq },
+        }
+    }
+}
+
+fn output_result(finished: &Finished, is_late: bool, undo: &UndoTracker, history: &History, last: &Mutex<Option<Transcript>>, events: &Events) -> Outcome {
+    let config = &finished.app_config.0;
+
+    // A spoken undo takes back the previous transcription. It's handled here, in order,
+    // so it can't overtake the transcription it refers to. The phrase is looked for in
+    // what was said, since rules or spoken commands may have changed the output.
+    if undo::is_undo_phrase(&finished.raw_text, config) {
+        if is_late {
+            warn!("Ignoring a late undo phrase");
+        } else if let Err(e) = undo.undo(config) {
+            error!("Failed to undo: {:?}", e);
+        }
+        return Outcome::Undone;
+    }
+
+    // Only the text transcribed from the same recording is replaced. If something else
+    // has been inserted since, the new text goes to the late sinks rather than next to it.
+    let mut is_late = is_late;
+    if finished.retranscribe && config.retranscribe.replace && !is_late {
+        let replaced = match &finished.transcript.recording {
+            Some(recording) => undo.undo_recording(config, recording),
+            None => Ok(false),
+        };
+        match replaced {
+            Ok(true) => {},
+            Ok(false) => {
+                warn!("The text from that recording isn't the last thing inserted, so it isn't replaced");
+                is_late = true;
+            },
+            Err(e) => {
+                warn!("Couldn't remove the previous transcription: {:#}", e);
+                is_late = true;
+            },
+        }
+    }
+
+    if is_late {
+        info!("Transcription arrived late, sending it to the late sinks");
+        output::deliver(&finished.transcript, &config.transcription.late_sinks, config, undo, events);
+    } else {
+        output::deliver(&finished.transcript, &finished.profile.output.sinks(), config, undo, events);
+    }
+    *last.lock().unwrap() = Some(finished.transcript.clone());
+
+    if config.history.enabled {
+        if let Err(e) = history.append(finished.entry.clone()) {
+            error!("Failed to add the transcription to the history: {:?}", e);
+        }
+    }
+
+    Outcome::Delivered { late: is_late }
+}
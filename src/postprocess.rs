@@ -0,0 +1,22 @@
+use crate::config::Profile;
+use crate::rules::Rules;
+use crate::spoken;
+
+/// Cleans up a transcription according to the profile's post-processing settings, then
+/// applies the replacement and snippet rules
+pub fn process(text: &str, profile: &Profile, rules: &Rules) -> String {
+    let config = &profile.postprocess;
+    let text = match config.spoken_commands {
+        true => spoken::apply(text.trim(), config),
+        false => text.trim().to_string(),
+    };
+    let text = rules.apply(&text, &profile.name);
+
+    // We leave a space at the end so that there's a space between this
+    // transcription and the next one, unless it already ends with a line break or space
+    if config.trailing_space && !text.is_empty() && !text.ends_with(char::is_whitespace) {
+        format!("{} ", text)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::*;
+
+/// The last few transcribed recordings, kept so they can be transcribed again with a
+/// different profile. Files are named by the time they were stored, so sorting by
+/// name sorts them by age.
+#[derive(Debug, Clone)]
+pub struct RecordingCache {
+    dir: PathBuf,
+}
+
+impl RecordingCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Moves a transcribed recording into the cache and deletes the oldest ones beyond
+    /// `keep`. With `keep` 0 the recording is just deleted. Returns the cached path.
+    pub fn store(&self, path: &Path, keep: usize) -> Result<Option<PathBuf>> {
+        if keep == 0 {
+            fs::remove_file(path).context(format!("Failed to delete file {}", path.display()))?;
+            return Ok(None);
+        }
+
+        let cached = self.add(path)?;
+
+        for old in self.list()?.into_iter().skip(keep) {
+            if let Err(e) = fs::remove_file(&old) {
+                error!("Failed to delete file {}: {:?}", old.display(), e);
+            }
+        }
+
+        Ok(Some(cached))
+    }
+
+    /// Moves a recording into the cache without deleting old ones. Returns the cached path.
+    /// It's named by when the recording was made, so recordings transcribed out of
+    /// order still sort by age.
+    pub fn add(&self, path: &Path) -> Result<PathBuf> {
+        let recorded = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::<Local>::from)
+            .unwrap_or_else(|_| Local::now());
+        let name = format!("{}.wav", recorded.format("%Y%m%d-%H%M%S%.3f"));
+        let cached = self.dir.join(name);
+        fs::rename(path, &cached)
+            .context(format!("Failed to move {} to {}", path.display(), cached.display()))?;
+        debug!("Cached recording {}", cached.display());
+        Ok(cached)
+    }
+
+    /// Cached recordings, newest first
+    pub fn list(&self) -> Result<Vec<PathBuf>> {
+        let mut recordings = fs::read_dir(&self.dir)
+            .context(format!("Failed to read {}", self.dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wav"))
+            .collect::<Vec<_>>();
+        recordings.sort();
+        recordings.reverse();
+        Ok(recordings)
+    }
+
+    /// The nth newest recording, starting at 1 for the latest
+    pub fn nth_latest(&self, n: usize) -> Result<PathBuf> {
+        let recordings = self.list()?;
+        match n.checked_sub(1).and_then(|index| recordings.get(index)) {
+            Some(path) => Ok(path.clone()),
+            None if recordings.is_empty() => Err(anyhow::anyhow!("No recordings are cached, see `recordings.keep` in the config")),
+            None => Err(anyhow::anyhow!("Only {} recording(s) are cached", recordings.len())),
+        }
+    }
+}
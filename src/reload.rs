@@ -0,0 +1,100 @@
+use anyhow::{bail, Context, Result};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use crate::config::{Overrides, SharedConfig};
+use crate::hotkeys::{ListenerEvent, ListenerProxy};
+use crate::paths::Paths;
+use crate::rules::{self, SharedRules};
+use log::*;
+
+/// Editors often save in several steps (write temp file, rename, chmod), so wait this
+/// long after the first change before reloading
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Watches config.yaml and apikey.yaml and swaps the reloaded config into `app_config`.
+/// If any hotkey changed, the listener is told to register the new ones. A config that
+/// fails to parse or validate is logged and the old one is kept. rules.yaml is
+/// reloaded into `rules` in the same way.
+pub fn watch(paths: Paths, overrides: Overrides, app_config: SharedConfig, rules: SharedRules, listener: ListenerProxy) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+
+    // Watch the directory rather than the files, so files replaced by rename are still seen
+    let config_dir = paths.config_file.parent().unwrap_or(Path::new("."));
+    watcher.watch(config_dir, RecursiveMode::NonRecursive)
+        .context(format!("Failed to watch config directory: {}", config_dir.display()))?;
+
+    debug!("Watching {} for config changes", config_dir.display());
+
+    thread::spawn(move || {
+        // The watcher stops when dropped, so keep it alive for as long as this thread runs
+        let _watcher = watcher;
+
+        while let Ok(event) = receiver.recv() {
+            let mut changed = match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => event.paths,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!("Config watcher error: {:?}", e);
+                    continue;
+                },
+            };
+
+            thread::sleep(DEBOUNCE);
+            while let Ok(event) = receiver.try_recv() {
+                if let Ok(event) = event {
+                    changed.extend(event.paths);
+                }
+            }
+
+            if changed.iter().any(|p| is_watched_file(p, &paths)) {
+                match reload(&paths, &overrides, &app_config, &listener) {
+                    Ok(()) => info!("Reloaded config from {}", paths.config_file.display()),
+                    Err(e) => error!("Failed to reload config, keeping the old one: {:#}", e),
+                }
+            }
+            if changed.iter().any(|p| p.file_name() == paths.rules_file.file_name()) {
+                match rules::reload(&paths.rules_file, &rules) {
+                    Ok(()) => info!("Reloaded rules from {}", paths.rules_file.display()),
+                    Err(e) => error!("Failed to reload rules, keeping the old ones: {:#}", e),
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn is_watched_file(path: &Path, paths: &Paths) -> bool {
+    path.file_name() == paths.config_file.file_name() || path.file_name() == paths.api_key_file.file_name()
+}
+
+/// Loads the config files again and swaps them into `app_config`, telling the listener
+/// about changed hotkeys. On error the current config is kept. Unlike at startup, a
+/// missing file is an error rather than created with the defaults.
+pub fn reload(paths: &Paths, overrides: &Overrides, app_config: &SharedConfig, listener: &ListenerProxy) -> Result<()> {
+    for path in [&paths.config_file, &paths.api_key_file] {
+        if !path.exists() {
+            bail!("{} was removed", path.display());
+        }
+    }
+
+    let new_config = crate::load_config(paths, overrides)?;
+
+    let old_config = app_config.load();
+    if old_config.0.listener.backend != new_config.0.listener.backend || old_config.0.listener.devices != new_config.0.listener.devices {
+        warn!("Restart Talky to use the new listener settings");
+    }
+    let bindings = new_config.0.hotkey_bindings();
+    if old_config.0.hotkey_bindings() != bindings {
+        if let Err(e) = listener.send_event(ListenerEvent::SetBindings(bindings)) {
+            error!("Failed to send new hotkeys to the listener: {:?}", e);
+        }
+    }
+
+    app_config.store(Arc::new(new_config));
+    Ok(())
+}
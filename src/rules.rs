@@ -0,0 +1,252 @@
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use regex::{NoExpand, Regex};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use log::*;
+
+/// The live rules. A reload swaps in the new ones.
+pub type SharedRules = Arc<ArcSwap<Rules>>;
+
+/// rules.yaml: rules for every profile, and more rules for particular profiles
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    #[serde(default)]
+    snippets: BTreeMap<String, String>,
+    #[serde(default)]
+    profiles: BTreeMap<String, RuleSetConfig>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RuleSetConfig {
+    #[serde(default)]
+    rules: Vec<RuleConfig>,
+    /// Spoken trigger phrase to the text it expands to
+    #[serde(default)]
+    snippets: BTreeMap<String, String>,
+}
+
+/// A replacement, e.g. `{ match: "talkie", replace: "Talky", kind: ignore_case }`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    /// Text or regular expression to look for
+    #[serde(rename = "match")]
+    pattern: String,
+    /// What it's replaced with. Regex rules can use $1 or ${name} for groups.
+    replace: String,
+    #[serde(default)]
+    kind: MatchKind,
+    /// Only match whole words, not parts of longer ones
+    #[serde(default = "default_true")]
+    whole_word: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How a rule's `match` is compared with the text
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum MatchKind {
+    /// The exact text
+    #[default]
+    Literal,
+    /// The text in any mix of upper and lower case
+    IgnoreCase,
+    /// A regular expression, see https://docs.rs/regex/latest/regex/#syntax
+    Regex,
+}
+
+/// A compiled rule or snippet
+#[derive(Debug)]
+struct Rule {
+    regex: Regex,
+    replacement: String,
+    /// Expand $1 and ${name} in the replacement
+    expand: bool,
+}
+
+impl Rule {
+    fn compile(config: &RuleConfig) -> Result<Self> {
+        let pattern = match config.kind {
+            MatchKind::Literal => regex::escape(&config.pattern),
+            MatchKind::IgnoreCase => format!("(?i){}", regex::escape(&config.pattern)),
+            MatchKind::Regex => format!("(?:{})", config.pattern),
+        };
+        let pattern = match (config.whole_word, config.kind) {
+            // What a regex matches isn't known up front, so it's bounded on both sides
+            (true, MatchKind::Regex) => format!(r"\b{}\b", pattern),
+            (true, _) => word_bounded(&pattern, &config.pattern),
+            (false, _) => pattern,
+        };
+        Ok(Self {
+            regex: Regex::new(&pattern).context(format!("Invalid pattern '{}'", config.pattern))?,
+            replacement: config.replace.clone(),
+            expand: config.kind == MatchKind::Regex,
+        })
+    }
+
+    /// Snippets match their trigger as whole words in any case, with any commas the
+    /// model put between the words and a full stop it put after them
+    fn snippet(trigger: &str, text: &str) -> Result<Self> {
+        let words: Vec<String> = trigger.split_whitespace().map(regex::escape).collect();
+        anyhow::ensure!(!words.is_empty(), "Snippet trigger is empty");
+        let pattern = format!(r"(?i)\b{}\b[.!]?", words.join(r"[\s,]+"));
+        Ok(Self {
+            regex: Regex::new(&pattern).context(format!("Invalid snippet trigger '{}'", trigger))?,
+            replacement: text.to_string(),
+            expand: false,
+        })
+    }
+
+    fn apply(&self, text: &str) -> String {
+        match self.expand {
+            true => self.regex.replace_all(text, self.replacement.as_str()).into_owned(),
+            false => self.regex.replace_all(text, NoExpand(&self.replacement)).into_owned(),
+        }
+    }
+}
+
+// \b only works next to a word character, so text such as "C++" is bounded on the
+// side that starts or ends with one
+fn word_bounded(pattern: &str, text: &str) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = if is_word(text.chars().next()) { r"\b" } else { "" };
+    let end = if is_word(text.chars().last()) { r"\b" } else { "" };
+    format!("{}{}{}", start, pattern, end)
+}
+
+#[derive(Debug, Default)]
+struct RuleSet {
+    rules: Vec<Rule>,
+    snippets: Vec<Rule>,
+}
+
+impl RuleSet {
+    fn compile(config: &RuleSetConfig, prefix: &str) -> Result<Self> {
+        let rules = config.rules.iter()
+            .enumerate()
+            .map(|(index, rule)| Rule::compile(rule).context(format!("{}rules.{}", prefix, index)))
+            .collect::<Result<_>>()?;
+        let snippets = config.snippets.iter()
+            .map(|(trigger, text)| Rule::snippet(trigger, text).context(format!("{}snippets.{}", prefix, trigger)))
+            .collect::<Result<_>>()?;
+        Ok(Self { rules, snippets })
+    }
+}
+
+/// Replacements and snippets applied to each transcription before it is output
+#[derive(Debug, Default)]
+pub struct Rules {
+    common: RuleSet,
+    profiles: BTreeMap<String, RuleSet>,
+}
+
+impl Rules {
+    /// Loads and compiles the rules file. No file means no rules.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .context(format!("Failed to read rules file: {}", path.display()))?;
+        let file: RulesFile = serde_yaml::from_str::<Option<RulesFile>>(&contents)
+            .context(format!("Failed to parse rules file: {}", path.display()))?
+            .unwrap_or_default();
+
+        let common = RuleSetConfig { rules: file.rules, snippets: file.snippets };
+        let common = RuleSet::compile(&common, "")
+            .context(format!("Invalid rule in {}", path.display()))?;
+        let profiles = file.profiles.iter()
+            .map(|(name, set)| Ok((name.clone(), RuleSet::compile(set, &format!("profiles.{}.", name))?)))
+            .collect::<Result<_>>()
+            .context(format!("Invalid rule in {}", path.display()))?;
+
+        let rules = Self { common, profiles };
+        debug!("Loaded {} rule(s) and {} snippet(s) from {}", rules.count(|s| &s.rules), rules.count(|s| &s.snippets), path.display());
+        Ok(rules)
+    }
+
+    /// Applies the rules for every profile and then the profile's own, then expands
+    /// snippets. Snippets come last so a rule can fix a misheard trigger, and the text
+    /// a snippet inserts isn't changed by the rules. A profile's snippet takes
+    /// precedence over one for every profile.
+    pub fn apply(&self, text: &str, profile: &str) -> String {
+        let profile = self.profiles.get(profile);
+        let rules = self.common.rules.iter().chain(profile.into_iter().flat_map(|p| &p.rules));
+        let snippets = profile.into_iter().flat_map(|p| &p.snippets).chain(&self.common.snippets);
+
+        rules.chain(snippets).fold(text.to_string(), |text, rule| rule.apply(&text))
+    }
+
+    /// The profiles rules.yaml has rules or snippets for
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    fn count(&self, list: impl Fn(&RuleSet) -> &Vec<Rule>) -> usize {
+        list(&self.common).len() + self.profiles.values().map(|set| list(set).len()).sum::<usize>()
+    }
+}
+
+/// Loads the rules file again and swaps it into `rules`. On error the current rules are kept.
+pub fn reload(path: &Path, rules: &SharedRules) -> Result<()> {
+    rules.store(Arc::new(Rules::load(path)?));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(yaml: &str) -> Rules {
+        let path = std::env::temp_dir().join(format!("talky-rules-{}-{:?}.yaml", std::process::id(), std::thread::current().id()));
+        std::fs::write(&path, yaml).unwrap();
+        let rules = Rules::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        rules.unwrap()
+    }
+
+    #[test]
+    fn literal_rules_match_whole_words() {
+        let rules = rules("rules:\n  - match: talkie\n    replace: Talky\n    kind: ignore_case\n  - match: C++\n    replace: Rust\n");
+        assert_eq!(rules.apply("Talkie and talkies in C++.", "default"), "Talky and talkies in Rust.");
+    }
+
+    #[test]
+    fn regex_rules_are_bounded_on_both_sides() {
+        let rules = rules("rules:\n  - match: 'cat|dog'\n    replace: pet\n    kind: regex\n  - match: '(\\d+) percent'\n    replace: '$1%'\n    kind: regex\n");
+        assert_eq!(rules.apply("concatenate the cat and hotdog, 5 percent", "default"), "concatenate the pet and hotdog, 5%");
+    }
+
+    #[test]
+    fn profile_rules_run_after_the_common_ones() {
+        let rules = rules("rules:\n  - match: a\n    replace: b\nprofiles:\n  docs:\n    rules:\n      - match: b\n        replace: c\n");
+        assert_eq!(rules.apply("a", "default"), "b");
+        assert_eq!(rules.apply("a", "docs"), "c");
+        assert_eq!(rules.profile_names().collect::<Vec<_>>(), vec!["docs"]);
+    }
+
+    #[test]
+    fn snippets_expand_after_the_rules() {
+        let rules = rules("rules:\n  - match: sig nature\n    replace: signature\n  - match: Jo\n    replace: Joe\nsnippets:\n  insert signature: \"Best,\\nJo\"\n");
+        assert_eq!(rules.apply("Thanks. Insert, sig nature.", "default"), "Thanks. Best,\nJo");
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let path = std::env::temp_dir().join(format!("talky-rules-invalid-{}.yaml", std::process::id()));
+        std::fs::write(&path, "rules:\n  - match: '('\n    replace: x\n    kind: regex\n").unwrap();
+        let error = Rules::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(format!("{:#}", error).contains("rules.0"));
+    }
+}
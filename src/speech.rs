@@ -6,23 +6,29 @@ use serde_json::Value;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
-use std::sync::Arc;
-use crate::config::{Config, ApiKeyConfig};
+use crate::config::{ApiConfig, ApiKeyConfig, Backend};
 use log::*;
 
+/// What the speech-to-text API returned
+#[derive(Debug, Clone)]
+pub struct Transcription {
+    pub text: String,
+    /// How sure the model was, 0.0 to 1.0, from the average log probability of the
+    /// segments. Only whisper models report it.
+    pub confidence: Option<f32>,
+}
+
 /// Takes a path to an audio file, sends it to the speech-to-text API,
-/// and returns the transcribed text
-pub fn transcribe_audio(file_path: &str, app_config: &Arc<(Config, ApiKeyConfig)>) -> Result<String> {
-    let (config, api_key) = &**app_config;
-    
-    debug!("Preparing to transcribe audio file: {}", file_path);
-    debug!("Using API URL: {}", config.api.url);
+/// and returns the transcribed text. With `want_confidence`, whisper models are asked
+/// for the segments too, so the confidence can be worked out.
+pub fn transcribe_audio(file_path: &Path, api: &ApiConfig, api_key: &ApiKeyConfig, want_confidence: bool) -> Result<Transcription> {
+    debug!("Preparing to transcribe audio file: {}", file_path.display());
+    debug!("Using API URL: {}", api.url);
     
     // Create a reqwest client
     let client = Client::new();
     
     // Verify the file exists and has content
-    let file_path = Path::new(file_path);
     let metadata = std::fs::metadata(file_path)
         .context(format!("Failed to get metadata for file: {}", file_path.display()))?;
     
@@ -51,13 +57,21 @@ pub fn transcribe_audio(file_path: &str, app_config: &Arc<(Config, ApiKeyConfig)
     // Create multipart form
     let mut form = Form::new()
         .part("file", Part::bytes(file_content).file_name(file_name.to_string()))
-        .text("temperature", config.api.temperature.to_string())
-        .text("temperature_inc", config.api.temperature_inc.to_string())
-        .text("response_format", "json".to_string())
-        .text("model", config.api.model.to_string());
+        .text("temperature", api.temperature.to_string())
+        .text("temperature_inc", api.temperature_inc.to_string())
+        .text("response_format", response_format(api, want_confidence).to_string());
 
-    if config.api.prompt != "" {
-        form = form.text("prompt", config.api.prompt.to_string());
+    // whisper.cpp's server runs the model it was started with
+    if api.backend != Backend::WhisperCpp {
+        form = form.text("model", api.model.to_string());
+    }
+
+    if !api.prompt.is_empty() {
+        form = form.text("prompt", api.prompt.to_string());
+    }
+
+    if !api.language.is_empty() {
+        form = form.text("language", api.language.to_string());
     }
     
     // Check if the API key is already in Bearer format
@@ -74,24 +88,20 @@ pub fn transcribe_audio(file_path: &str, app_config: &Arc<(Config, ApiKeyConfig)
     }
     
     // Send the request to configured URL with API key in header
-    let response = client.post(&config.api.url)
+    let response = client.post(&api.url)
         .headers(headers)
         .multipart(form)
         .send()
         .context("Failed to send request to speech-to-text API")?;
 
-    // Delete the file
-    std::fs::remove_file(file_path)
-        .context(format!("Failed to delete file: {}", file_path.display()))?;
-    
     // Check if the request was successful
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().unwrap_or_default();
         return Err(anyhow::anyhow!(
-            "API request failed with status: {}, body: {}", 
+            "API request failed with status {}: {}", 
             status,
-            error_text
+            error_message(&error_text)
         ));
     }
     
@@ -103,13 +113,9 @@ pub fn transcribe_audio(file_path: &str, app_config: &Arc<(Config, ApiKeyConfig)
     match serde_json::from_str::<Value>(&response_text) {
         Ok(json) => {
             if let Some(text) = json.get("text").and_then(|t| t.as_str()) {
-
-                // Strip whitespace and return. We leave a space at the end so that 
-                // there's a space between this transcription and the next one
-                Ok(text.trim().to_string() + " ")
+                Ok(Transcription { text: text.trim().to_string(), confidence: confidence(&json) })
             } else {
-                // TODO maybe try to extract error message
-                Err(anyhow::anyhow!(response_text))
+                Err(anyhow::anyhow!(error_message(&response_text)))
             }
         },
         Err(_) => {
@@ -117,3 +123,50 @@ pub fn transcribe_audio(file_path: &str, app_config: &Arc<(Config, ApiKeyConfig)
         }
     }
 }
+
+/// Returns true if the error is from not being able to reach the API at all, as
+/// opposed to the API rejecting the request
+pub fn is_unreachable(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| {
+        e.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_connect() || e.is_timeout())
+    })
+}
+
+// Only whisper models return segments with log probabilities, and the gpt-4o models
+// reject verbose_json. It's a bigger response, so it's only asked for when needed.
+fn response_format(api: &ApiConfig, confidence: bool) -> &'static str {
+    if confidence && (api.backend == Backend::WhisperCpp || api.model.starts_with("whisper")) {
+        "verbose_json"
+    } else {
+        "json"
+    }
+}
+
+// Average probability of the segments, from verbose_json's avg_logprob
+fn confidence(json: &Value) -> Option<f32> {
+    let logprobs: Vec<f64> = json.get("segments")?
+        .as_array()?
+        .iter()
+        .filter_map(|s| s.get("avg_logprob").and_then(|p| p.as_f64()))
+        .collect();
+    if logprobs.is_empty() {
+        return None;
+    }
+    let mean = logprobs.iter().sum::<f64>() / logprobs.len() as f64;
+    Some(mean.exp() as f32)
+}
+
+// The reason given in an error response. OpenAI sends {"error": {"message": ...}} and
+// whisper.cpp {"error": "..."}; anything else is returned as is.
+fn error_message(body: &str) -> String {
+    let Ok(json) = serde_json::from_str::<Value>(body) else {
+        return body.trim().to_string();
+    };
+    let error = json.get("error");
+    error
+        .and_then(|e| e.get("message"))
+        .or(error)
+        .and_then(|m| m.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| body.trim().to_string())
+}
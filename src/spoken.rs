@@ -0,0 +1,267 @@
+use crate::config::{PostProcessConfig, SpokenCommand};
+
+/// English phrases for each command, used unless `builtin_commands` is turned off
+const BUILTIN_COMMANDS: &[(&str, SpokenCommand)] = &[
+    ("period", SpokenCommand::Period),
+    ("full stop", SpokenCommand::Period),
+    ("comma", SpokenCommand::Comma),
+    ("question mark", SpokenCommand::QuestionMark),
+    ("exclamation mark", SpokenCommand::ExclamationMark),
+    ("exclamation point", SpokenCommand::ExclamationMark),
+    ("colon", SpokenCommand::Colon),
+    ("semicolon", SpokenCommand::Semicolon),
+    ("new line", SpokenCommand::NewLine),
+    ("newline", SpokenCommand::NewLine),
+    ("new paragraph", SpokenCommand::NewParagraph),
+    ("open quote", SpokenCommand::OpenQuote),
+    ("close quote", SpokenCommand::CloseQuote),
+    ("end quote", SpokenCommand::CloseQuote),
+    ("open paren", SpokenCommand::OpenParen),
+    ("open parenthesis", SpokenCommand::OpenParen),
+    ("close paren", SpokenCommand::CloseParen),
+    ("close parenthesis", SpokenCommand::CloseParen),
+    ("bullet point", SpokenCommand::BulletPoint),
+    ("all caps", SpokenCommand::AllCaps),
+    ("end caps", SpokenCommand::EndCaps),
+    ("no space", SpokenCommand::NoSpace),
+];
+
+/// Punctuation the model may have put after a word, which a spoken punctuation
+/// command replaces
+const MODEL_PUNCTUATION: &[char] = &['.', ',', '?', '!', ':', ';'];
+
+/// Replaces spoken commands in a transcription with the formatting they stand for.
+/// Commands are matched as whole words, ignoring case and any punctuation the model
+/// added to them. Words keep the whitespace that was between them.
+pub fn apply(text: &str, config: &PostProcessConfig) -> String {
+    let commands = command_table(config);
+    let escape = words(&config.escape_phrase);
+    let (separators, tokens): (Vec<&str>, Vec<&str>) = tokens(text).into_iter().unzip();
+    let normalized: Vec<String> = tokens.iter().map(|t| normalize(t)).collect();
+
+    let mut writer = Writer::default();
+    let mut index = 0;
+    while index < tokens.len() {
+        // The escape phrase makes the command after it plain words
+        if !escape.is_empty() && starts_with(&normalized[index..], &escape) {
+            let after = index + escape.len();
+            if let Some((phrase, _)) = find_command(&commands, &normalized[after..]) {
+                for i in after..after + phrase.len() {
+                    writer.word(separators[i], tokens[i]);
+                }
+                index = after + phrase.len();
+                continue;
+            }
+        }
+
+        match find_command(&commands, &normalized[index..]) {
+            Some((phrase, command)) => {
+                writer.command(*command);
+                index += phrase.len();
+            },
+            None => {
+                writer.word(separators[index], tokens[index]);
+                index += 1;
+            },
+        }
+    }
+
+    writer.out
+}
+
+/// Whether the phrase is exactly one of the commands
+pub fn is_command(phrase: &str, config: &PostProcessConfig) -> bool {
+    let phrase = words(phrase);
+    !phrase.is_empty() && command_table(config).iter().any(|(command, _)| *command == phrase)
+}
+
+// The commands as lists of words, longest first so "new paragraph" wins over a
+// shorter phrase it starts with
+fn command_table(config: &PostProcessConfig) -> Vec<(Vec<String>, SpokenCommand)> {
+    let builtin = BUILTIN_COMMANDS.iter()
+        .filter(|_| config.builtin_commands)
+        .map(|(phrase, command)| (words(phrase), *command));
+    let custom = config.commands.iter().map(|(phrase, command)| (words(phrase), *command));
+
+    let mut table: Vec<(Vec<String>, SpokenCommand)> = custom.chain(builtin)
+        .filter(|(phrase, _)| !phrase.is_empty())
+        .collect();
+    table.sort_by_key(|(phrase, _)| std::cmp::Reverse(phrase.len()));
+    table
+}
+
+fn find_command<'a>(commands: &'a [(Vec<String>, SpokenCommand)], words: &[String]) -> Option<&'a (Vec<String>, SpokenCommand)> {
+    commands.iter().find(|(phrase, _)| starts_with(words, phrase))
+}
+
+fn starts_with(words: &[String], phrase: &[String]) -> bool {
+    words.len() >= phrase.len() && words.iter().zip(phrase).all(|(word, p)| word == p)
+}
+
+// The words of the text, each with the whitespace before it
+fn tokens(text: &str) -> Vec<(&str, &str)> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        let word = &rest[start..];
+        let end = word.find(char::is_whitespace).unwrap_or(word.len());
+        tokens.push((&rest[..start], &word[..end]));
+        rest = &word[end..];
+    }
+    tokens
+}
+
+fn words(phrase: &str) -> Vec<String> {
+    phrase.split_whitespace().map(normalize).filter(|w| !w.is_empty()).collect()
+}
+
+// Lowercase, without punctuation around it
+fn normalize(token: &str) -> String {
+    token.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+/// Builds the formatted text a word or command at a time
+#[derive(Default)]
+struct Writer {
+    out: String,
+    /// The next word is joined on without a space
+    no_space: bool,
+    /// The next word starts a sentence or line
+    capitalize: bool,
+    all_caps: bool,
+    /// The last thing written was a word, rather than a command
+    after_word: bool,
+}
+
+impl Writer {
+    fn word(&mut self, separator: &str, word: &str) {
+        if !self.no_space && !self.out.is_empty() && !self.out.ends_with(char::is_whitespace) {
+            // Between two words the whitespace from the transcription is kept, so line
+            // breaks the model put in aren't lost
+            match self.after_word {
+                true => self.out.push_str(separator),
+                false => self.out.push(' '),
+            }
+        }
+
+        let mut word = match self.all_caps {
+            true => word.to_uppercase(),
+            false => word.to_string(),
+        };
+        if self.capitalize {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                word = first.to_uppercase().chain(chars).collect();
+            }
+        }
+
+        self.out.push_str(&word);
+        self.no_space = false;
+        self.capitalize = false;
+        self.after_word = true;
+    }
+
+    fn command(&mut self, command: SpokenCommand) {
+        self.after_word = false;
+        match command {
+            SpokenCommand::Period => self.punctuation('.', true),
+            SpokenCommand::Comma => self.punctuation(',', false),
+            SpokenCommand::QuestionMark => self.punctuation('?', true),
+            SpokenCommand::ExclamationMark => self.punctuation('!', true),
+            SpokenCommand::Colon => self.punctuation(':', false),
+            SpokenCommand::Semicolon => self.punctuation(';', false),
+            SpokenCommand::NewLine => self.line_break("\n"),
+            SpokenCommand::NewParagraph => self.line_break("\n\n"),
+            SpokenCommand::OpenQuote => self.open("\""),
+            SpokenCommand::CloseQuote => self.close("\""),
+            SpokenCommand::OpenParen => self.open("("),
+            SpokenCommand::CloseParen => self.close(")"),
+            SpokenCommand::BulletPoint => {
+                self.out.truncate(self.out.trim_end_matches(' ').len());
+                if !self.out.is_empty() && !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+                self.out.push_str("- ");
+                self.capitalize = true;
+            },
+            SpokenCommand::AllCaps => self.all_caps = true,
+            SpokenCommand::EndCaps => self.all_caps = false,
+            SpokenCommand::NoSpace => self.no_space = true,
+        }
+    }
+
+    // Goes straight after the previous word, in place of any punctuation the model put there
+    fn punctuation(&mut self, mark: char, ends_sentence: bool) {
+        self.out.truncate(self.out.trim_end_matches(' ').trim_end_matches(MODEL_PUNCTUATION).len());
+        self.out.push(mark);
+        self.no_space = false;
+        self.capitalize = ends_sentence;
+    }
+
+    fn line_break(&mut self, text: &str) {
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        self.out.push_str(text);
+        self.capitalize = true;
+    }
+
+    fn open(&mut self, mark: &str) {
+        if !self.out.is_empty() && !self.out.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+        self.out.push_str(mark);
+        self.no_space = true;
+    }
+
+    fn close(&mut self, mark: &str) {
+        self.out.truncate(self.out.trim_end_matches(' ').trim_end_matches(',').len());
+        self.out.push_str(mark);
+        self.no_space = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_default(text: &str) -> String {
+        apply(text, &PostProcessConfig { spoken_commands: true, ..Default::default() })
+    }
+
+    #[test]
+    fn punctuation_replaces_what_the_model_added() {
+        assert_eq!(apply_default("Hello comma how are you question mark"), "Hello, how are you?");
+        assert_eq!(apply_default("Done. Period. next one"), "Done. Next one");
+    }
+
+    #[test]
+    fn line_breaks_and_bullets() {
+        assert_eq!(apply_default("first new line second new paragraph third"), "first\nSecond\n\nThird");
+        assert_eq!(apply_default("list bullet point eggs bullet point milk"), "list\n- Eggs\n- Milk");
+    }
+
+    #[test]
+    fn whitespace_between_words_is_kept() {
+        assert_eq!(apply_default("Dear Sam,\n\nthanks  for\tthe note period"), "Dear Sam,\n\nthanks  for\tthe note.");
+    }
+
+    #[test]
+    fn quotes_caps_and_no_space() {
+        assert_eq!(apply_default("he said open quote hi close quote"), "he said \"hi\"");
+        assert_eq!(apply_default("all caps very loud end caps quiet"), "VERY LOUD quiet");
+        assert_eq!(apply_default("foo no space bar"), "foobar");
+    }
+
+    #[test]
+    fn escape_phrase_writes_the_words() {
+        assert_eq!(apply_default("say literal comma please"), "say comma please");
+    }
+
+    #[test]
+    fn custom_commands_and_no_builtins() {
+        let mut config = PostProcessConfig { spoken_commands: true, builtin_commands: false, ..Default::default() };
+        config.commands.insert("punto".to_string(), SpokenCommand::Period);
+        assert_eq!(apply("hola punto comma", &config), "hola. Comma");
+        assert!(is_command("Punto.", &config));
+        assert!(!is_command("period", &config));
+    }
+}
@@ -0,0 +1,412 @@
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::app::App;
+use crate::config::{SharedConfig, DEFAULT_PROFILE};
+use crate::control::Control;
+use crate::events::AppEvent;
+use crate::history::{Entry, History};
+use crate::hotkeys::{ListenerEvent, ListenerProxy};
+use crate::undo::UndoTracker;
+use crate::{clipboard, paste};
+use log::*;
+
+/// Log lines kept for the log pane
+const LOG_LINES: usize = 200;
+
+/// How many history entries are shown
+const RECENT_ENTRIES: usize = 50;
+
+/// Finished transcriptions stay in the queue pane this long
+const FINISHED_JOB_TIME: Duration = Duration::from_secs(20);
+
+/// Time to switch to the target window before a re-paste
+const PASTE_COUNTDOWN: Duration = Duration::from_secs(3);
+
+/// Log lines captured for the TUI, which can't share the terminal with stderr logging
+pub type LogBuffer = Arc<Mutex<VecDeque<String>>>;
+
+/// A fern output that keeps the latest log lines in the buffer
+pub fn log_output(buffer: LogBuffer) -> fern::Output {
+    fern::Output::call(move |record| {
+        let mut buffer = buffer.lock().unwrap();
+        if buffer.len() == LOG_LINES {
+            buffer.pop_front();
+        }
+        buffer.push_back(format!("{} {}", record.level(), record.args()));
+    })
+}
+
+#[derive(Debug)]
+enum JobState {
+    Queued,
+    Transcribed { latency_ms: u64, text: String },
+    Delivered { latency_ms: u64, late: bool, text: String },
+    Failed(String),
+    /// A spoken undo phrase
+    Undone,
+}
+
+/// A transcription shown in the queue pane
+#[derive(Debug)]
+struct Job {
+    profile: String,
+    queued: Instant,
+    state: JobState,
+    finished: Option<Instant>,
+}
+
+struct Ui {
+    control: Arc<Control>,
+    app_config: SharedConfig,
+    history: Arc<History>,
+    undo: Arc<UndoTracker>,
+    listener: ListenerProxy,
+    events: mpsc::Receiver<AppEvent>,
+    logs: LogBuffer,
+
+    jobs: BTreeMap<u64, Job>,
+    /// Newest first
+    recent: Vec<Entry>,
+    selected: ListState,
+    /// Message shown in the status pane, with when it was set
+    message: Option<(String, Instant)>,
+    /// Transcription to paste once the countdown ends
+    pending_paste: Option<(Entry, Instant)>,
+}
+
+/// Runs the dashboard on top of a normal running Talky. Quitting the TUI stops Talky.
+pub fn run(app: App, logs: LogBuffer) -> Result<()> {
+    let mut ui = Ui {
+        control: app.control.clone(),
+        app_config: app.app_config.clone(),
+        history: app.history.clone(),
+        undo: app.undo.clone(),
+        listener: app.listener.clone(),
+        events: app.events.subscribe(),
+        logs,
+        jobs: BTreeMap::new(),
+        recent: Vec::new(),
+        selected: ListState::default().with_selected(Some(0)),
+        message: None,
+        pending_paste: None,
+    };
+    ui.load_history();
+
+    // The hotkey listener needs the main thread, so the UI gets its own. It keeps
+    // drawing while Talky shuts down, until `app.run` returns.
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut terminal = ratatui::init();
+    TERMINAL_IN_USE.store(true, Ordering::SeqCst);
+    let ui_stop = stop.clone();
+    let thread = thread::spawn(move || ui.run(&mut terminal, &ui_stop));
+    let mut guard = TerminalGuard { stop, thread: Some(thread) };
+
+    let result = app.run();
+    // The terminal is back to normal before any error is printed
+    let ui_result = guard.finish();
+    result.and(ui_result)
+}
+
+/// Stops the UI thread and puts the terminal back, however `run` ends
+struct TerminalGuard {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl TerminalGuard {
+    fn finish(&mut self) -> Result<()> {
+        self.stop.store(true, Ordering::SeqCst);
+        let result = match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(anyhow::anyhow!("The terminal UI panicked")),
+            None => Ok(()),
+        };
+        restore_terminal();
+        result
+    }
+}
+
+/// Set while the TUI has the terminal in raw mode on the alternate screen
+static TERMINAL_IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Puts the terminal back if the TUI is using it. Also called before exiting without
+/// a clean shutdown, which skips the guard in `run`.
+pub fn restore_terminal() {
+    if TERMINAL_IN_USE.swap(false, Ordering::SeqCst) {
+        ratatui::restore();
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            let _ = self.finish();
+        }
+    }
+}
+
+impl Ui {
+    fn run(&mut self, terminal: &mut DefaultTerminal, stop: &AtomicBool) -> Result<()> {
+        let result = self.draw_loop(terminal, stop);
+        // Without the UI there's no way to quit, so shut down. The error is returned
+        // from `run` once the terminal has been restored.
+        if result.is_err() {
+            let _ = self.listener.send_event(ListenerEvent::Shutdown);
+        }
+        result
+    }
+
+    fn draw_loop(&mut self, terminal: &mut DefaultTerminal, stop: &AtomicBool) -> Result<()> {
+        while !stop.load(Ordering::SeqCst) {
+            while let Ok(event) = self.events.try_recv() {
+                self.handle_event(event);
+            }
+            self.jobs.retain(|_, job| job.finished.is_none_or(|f| f.elapsed() < FINISHED_JOB_TIME));
+            self.paste_if_due();
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            // Redraw at least 10 times a second for the level meter
+            if !event::poll(Duration::from_millis(100))? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                // Quitting shuts Talky down like Ctrl+C does
+                KeyCode::Char('q') => {
+                    let _ = self.listener.send_event(ListenerEvent::Shutdown);
+                },
+                // Raw mode turns Ctrl+C into a key press
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    let _ = self.listener.send_event(ListenerEvent::Shutdown);
+                },
+                KeyCode::Char(' ') | KeyCode::Enter => {
+                    let _ = self.listener.send_event(ListenerEvent::Toggle(self.control.selected_profile()));
+                },
+                KeyCode::Char('s') => {
+                    let _ = self.listener.send_event(ListenerEvent::Stop);
+                },
+                KeyCode::Esc | KeyCode::Char('x') => {
+                    let _ = self.listener.send_event(ListenerEvent::Cancel);
+                },
+                KeyCode::Tab => self.next_profile(),
+                KeyCode::Up | KeyCode::Char('k') => self.selected.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.selected.select_next(),
+                KeyCode::Char('c') => self.copy_selected(),
+                KeyCode::Char('p') => self.paste_selected(),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Queued { seq, profile } => {
+                self.jobs.insert(seq, Job { profile, queued: Instant::now(), state: JobState::Queued, finished: None });
+            },
+            AppEvent::Transcribed { seq, profile, text, latency_ms, .. } => {
+                if let Some(job) = self.jobs.get_mut(&seq) {
+                    job.profile = profile;
+                    job.state = JobState::Transcribed { latency_ms, text };
+                }
+            },
+            AppEvent::Delivered { seq, late, text, .. } => {
+                if let Some(job) = self.jobs.get_mut(&seq) {
+                    let latency_ms = match job.state {
+                        JobState::Transcribed { latency_ms, .. } => latency_ms,
+                        _ => 0,
+                    };
+                    job.state = JobState::Delivered { latency_ms, late, text };
+                    job.finished = Some(Instant::now());
+                }
+                self.load_history();
+            },
+            AppEvent::TranscriptionFailed { seq, error, .. } => {
+                if let Some(job) = self.jobs.get_mut(&seq) {
+                    job.state = JobState::Failed(error);
+                    job.finished = Some(Instant::now());
+                }
+            },
+            AppEvent::RecordingStarted { profile } => self.show(&format!("Recording with {}", profile)),
+            AppEvent::RecordingStopped => {},
+            AppEvent::Undone { seq } => {
+                if let Some(job) = self.jobs.get_mut(&seq) {
+                    job.state = JobState::Undone;
+                    job.finished = Some(Instant::now());
+                }
+            },
+            AppEvent::RecordingCancelled => self.show("Recording cancelled"),
+            AppEvent::Error { message } => self.show(&message),
+        }
+    }
+
+    fn load_history(&mut self) {
+        match self.history.entries() {
+            Ok(entries) => {
+                self.recent = entries.into_iter().rev().take(RECENT_ENTRIES).collect();
+            },
+            Err(e) => self.show(&format!("Failed to read the history: {:#}", e)),
+        }
+    }
+
+    fn show(&mut self, message: &str) {
+        self.message = Some((message.to_string(), Instant::now()));
+    }
+
+    // Cycles through the default profile and the named ones
+    fn next_profile(&mut self) {
+        let config = self.app_config.load();
+        let names: Vec<&str> = std::iter::once(DEFAULT_PROFILE)
+            .chain(config.0.profiles.keys().map(String::as_str))
+            .collect();
+        let selected = self.control.selected_profile();
+        let index = names.iter().position(|n| *n == selected).map_or(0, |i| (i + 1) % names.len());
+        self.control.select_profile(names[index]);
+    }
+
+    fn selected_entry(&self) -> Option<&Entry> {
+        self.selected.selected().and_then(|i| self.recent.get(i))
+    }
+
+    fn copy_selected(&mut self) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        let message = match clipboard::copy_to_clipboard(&entry.text) {
+            Ok(()) => format!("Copied transcription {}", entry.id),
+            Err(e) => format!("Failed to copy: {:#}", e),
+        };
+        self.show(&message);
+    }
+
+    fn paste_selected(&mut self) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        self.pending_paste = Some((entry.clone(), Instant::now() + PASTE_COUNTDOWN));
+    }
+
+    fn paste_if_due(&mut self) {
+        let Some((_, at)) = &self.pending_paste else {
+            return;
+        };
+        if Instant::now() < *at {
+            return;
+        }
+
+        let Some((entry, _)) = self.pending_paste.take() else {
+            return;
+        };
+        let app_config = self.app_config.load_full();
+        let undo = self.undo.clone();
+        // Recorded like the original, so undo and redictate use its profile and recording
+        thread::spawn(move || match paste::paste_text(&entry.text, &app_config.0) {
+            Ok(true) => undo.record(&entry.text, &entry.profile, entry.audio.as_deref()),
+            Ok(false) => {},
+            Err(e) => error!("Failed to paste: {:?}", e),
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [status_area, level_area, queue_area, history_area, log_area, help_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Length(self.jobs.len().clamp(1, 6) as u16 + 2),
+            Constraint::Min(5),
+            Constraint::Length(8),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+
+        // Recording state and profile
+        let recording = self.control.is_recording();
+        let state = if self.control.is_shut_down() {
+            Span::styled(" STOPPING ", Style::new().fg(Color::Black).bg(Color::Gray).add_modifier(Modifier::BOLD))
+        } else if recording && self.control.is_paused() {
+            Span::styled(" ‖ PAUSED ", Style::new().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD))
+        } else if recording && self.control.is_hands_free() {
+            Span::styled(" ● HANDS-FREE ", Style::new().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD))
+        } else if recording {
+            Span::styled(" ● RECORDING ", Style::new().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD))
+        } else {
+            Span::styled(" IDLE ", Style::new().fg(Color::Black).bg(Color::Gray))
+        };
+        let profile = if recording { self.control.profile() } else { self.control.selected_profile() };
+        let mut status = vec![state, Span::raw(format!("  profile: {}", profile))];
+        if let Some((text, at)) = &self.message {
+            if at.elapsed() < Duration::from_secs(5) {
+                status.push(Span::raw(format!("  {}", text)).italic());
+            }
+        }
+        if let Some((_, at)) = &self.pending_paste {
+            let seconds = at.saturating_duration_since(Instant::now()).as_secs() + 1;
+            status.push(Span::raw(format!("  pasting in {}s, switch to the target window", seconds)).yellow());
+        }
+        frame.render_widget(Paragraph::new(Line::from(status)).block(Block::bordered().title(" Talky ")), status_area);
+
+        // Input level
+        let level = if recording { self.control.level().clamp(0.0, 1.0) } else { 0.0 };
+        let color = if level > 0.9 { Color::Red } else { Color::Green };
+        let gauge = Gauge::default()
+            .block(Block::bordered().title(" Input level "))
+            .gauge_style(Style::new().fg(color))
+            .ratio(level as f64)
+            .label(format!("{:.0}%", level * 100.0));
+        frame.render_widget(gauge, level_area);
+
+        // Queued and in-flight transcriptions
+        let jobs: Vec<ListItem> = self.jobs.iter().rev().take(6).map(|(seq, job)| {
+            let state = match &job.state {
+                JobState::Queued => format!("transcribing… {:.1}s", job.queued.elapsed().as_secs_f32()),
+                JobState::Transcribed { latency_ms, text } => format!("waiting to output ({} ms): {}", latency_ms, text),
+                JobState::Delivered { latency_ms, late: false, text } => format!("done ({} ms): {}", latency_ms, text),
+                JobState::Delivered { latency_ms, late: true, text } => {
+                    format!("late, sent to late sinks ({} ms): {}", latency_ms, text)
+                },
+                JobState::Failed(error) => format!("failed: {}", error),
+                JobState::Undone => "undid the previous transcription".to_string(),
+            };
+            ListItem::new(format!("#{:<4} {:<12} {}", seq + 1, job.profile, state))
+        }).collect();
+        frame.render_widget(List::new(jobs).block(Block::bordered().title(" Transcriptions ")), queue_area);
+
+        // Recent history
+        let entries: Vec<ListItem> = self.recent.iter().map(|entry| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} ", entry.time.format("%H:%M:%S"))).dark_gray(),
+                Span::raw(format!("{:<10} ", entry.profile)).cyan(),
+                Span::raw(entry.text.clone()),
+            ]))
+        }).collect();
+        let history = List::new(entries)
+            .block(Block::bordered().title(" History "))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(history, history_area, &mut self.selected);
+
+        // Log
+        let height = log_area.height.saturating_sub(2) as usize;
+        let logs = self.logs.lock().unwrap();
+        let lines: Vec<Line> = logs.iter().skip(logs.len().saturating_sub(height)).map(|l| Line::raw(l.clone())).collect();
+        drop(logs);
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Log ")), log_area);
+
+        let help = "space: record  s: stop  esc: cancel  tab: profile  ↑↓: select  c: copy  p: paste  q: quit";
+        frame.render_widget(Paragraph::new(help).dark_gray(), help_area);
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use enigo::{Enigo, Keyboard};
+use std::thread;
+use std::time::Duration;
+use crate::config::TypingConfig;
+
+/// Types text into the focused app with synthetic key presses instead of pasting.
+/// This works in apps that ignore or block paste, such as password fields and
+/// remote desktop sessions.
+pub fn type_text(text: &str, typing: &TypingConfig) -> Result<()> {
+    let mut enigo = Enigo::new(&enigo::Settings::default())?;
+
+    // Give the user time to release the hotkey, held modifiers would change the keys typed
+    thread::sleep(Duration::from_millis(typing.start_delay_ms));
+
+    let chars: Vec<char> = text.chars().collect();
+    let chunk_size = typing.chunk_size.max(1);
+
+    for (index, chunk) in chars.chunks(chunk_size).enumerate() {
+        if index > 0 {
+            thread::sleep(Duration::from_millis(typing.chunk_delay_ms));
+        }
+
+        if typing.char_delay_ms == 0 {
+            enigo.text(&chunk.iter().collect::<String>())?;
+            continue;
+        }
+
+        for (char_index, c) in chunk.iter().enumerate() {
+            if char_index > 0 {
+                thread::sleep(Duration::from_millis(typing.char_delay_ms));
+            }
+            enigo.text(&c.to_string())?;
+        }
+    }
+
+    Ok(())
+}
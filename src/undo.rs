@@ -0,0 +1,115 @@
+use anyhow::Result;
+use enigo::{Direction, Enigo, Key, Keyboard};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use crate::config::{Config, UndoMethod};
+use crate::keys::Chord;
+use crate::x11::{self, ActiveWindow};
+use log::*;
+
+/// Text that was pasted or typed into an app
+#[derive(Debug, Clone)]
+pub struct Insertion {
+    pub text: String,
+    pub profile: String,
+    /// Cached recording the text was transcribed from, if any
+    recording: Option<PathBuf>,
+    /// Focused window when the text was inserted, if known
+    window: Option<ActiveWindow>,
+}
+
+/// Remembers the last insertion so it can be taken back
+#[derive(Debug, Default)]
+pub struct UndoTracker {
+    last: Mutex<Option<Insertion>>,
+}
+
+impl UndoTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called after text has been pasted or typed into the focused app
+    pub fn record(&self, text: &str, profile: &str, recording: Option<&Path>) {
+        *self.last.lock().unwrap() = Some(Insertion {
+            text: text.to_string(),
+            profile: profile.to_string(),
+            recording: recording.map(Path::to_path_buf),
+            window: x11::active_window(),
+        });
+    }
+
+    /// Removes the last insertion from the focused app. Returns what was removed, or
+    /// None if there was nothing to undo. Each insertion can only be undone once.
+    pub fn undo(&self, config: &Config) -> Result<Option<Insertion>> {
+        let Some(insertion) = self.last.lock().unwrap().take() else {
+            info!("Nothing to undo");
+            return Ok(None);
+        };
+        remove(insertion, config).map(Some)
+    }
+
+    /// Removes the last insertion only if it was transcribed from `recording`, so a new
+    /// transcription of the same audio can take its place. Returns false, and leaves
+    /// the insertion alone, if something else has been inserted since.
+    pub fn undo_recording(&self, config: &Config, recording: &Path) -> Result<bool> {
+        let mut last = self.last.lock().unwrap();
+        match last.take() {
+            Some(insertion) if insertion.recording.as_deref() == Some(recording) => remove(insertion, config).map(|_| true),
+            other => {
+                *last = other;
+                Ok(false)
+            },
+        }
+    }
+}
+
+// Deletes an insertion from the focused app
+fn remove(insertion: Insertion, config: &Config) -> Result<Insertion> {
+    // Backspacing into a different app would delete something else
+    if let (Some(then), Some(now)) = (&insertion.window, x11::active_window()) {
+        if then.class != now.class || then.instance != now.instance {
+            return Err(anyhow::anyhow!("Focus moved from {} to {}, not undoing", then.class, now.class));
+        }
+    }
+
+    let mut enigo = Enigo::new(&enigo::Settings::default())?;
+
+    // Give the user time to release the hotkey
+    thread::sleep(Duration::from_millis(config.typing.start_delay_ms));
+
+    match &config.undo.method {
+        UndoMethod::Backspace => {
+            let count = insertion.text.chars().count();
+            for _ in 0..count {
+                enigo.key(Key::Backspace, Direction::Click)?;
+                if config.typing.char_delay_ms > 0 {
+                    thread::sleep(Duration::from_millis(config.typing.char_delay_ms));
+                }
+            }
+            debug!("Sent {} backspaces", count);
+        },
+        UndoMethod::Keys(keys) => keys.parse::<Chord>()?.send(&mut enigo)?,
+    }
+
+    info!("Removed: {}", insertion.text.trim());
+    Ok(insertion)
+}
+
+/// Whether a transcription is one of the spoken undo phrases, ignoring case and punctuation
+pub fn is_undo_phrase(text: &str, config: &Config) -> bool {
+    let normalized = normalize(text);
+    !normalized.is_empty() && config.undo.phrases.iter().any(|phrase| normalize(phrase) == normalized)
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,663 @@
+use anyhow::{Context, Result};
+use global_hotkey::hotkey::HotKey;
+use reqwest::Url;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::config::{ApiConfig, Backend, Config, HotkeyAction, HotkeyActions, HotkeyBackend, HotkeyBinding, OutputConfig, overlay, Overrides, PasteStrategy, PostProcessConfig, SinkConfig, UndoMethod, DEFAULT_PROFILE};
+use crate::keys::Chord;
+use crate::rules::Rules;
+use crate::spoken;
+use log::*;
+
+/// Models accepted by the OpenAI transcription endpoint
+const KNOWN_OPENAI_MODELS: &[&str] = &["whisper-1", "gpt-4o-transcribe", "gpt-4o-mini-transcribe"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in the config file
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Dotted path of the offending key, e.g. "api.temperature"
+    pub key: String,
+    /// 1-based line and column of the value in the YAML source, if it could be found
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+/// All diagnostics for one config file
+#[derive(Debug)]
+pub struct Report {
+    pub path: PathBuf,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.diagnostics.iter().filter(|d| d.severity == Severity::Error).count()
+    }
+
+    /// Writes every diagnostic to the log at the matching level
+    pub fn log(&self) {
+        for diagnostic in &self.diagnostics {
+            match diagnostic.severity {
+                Severity::Error => error!("{}", self.format(diagnostic)),
+                Severity::Warning => warn!("{}", self.format(diagnostic)),
+            }
+        }
+    }
+
+    /// Formats a diagnostic as "path:line:column: severity: key: message"
+    pub fn format(&self, diagnostic: &Diagnostic) -> String {
+        let location = match diagnostic.location {
+            Some((line, column)) => format!("{}:{}:{}", self.path.display(), line, column),
+            None => self.path.display().to_string(),
+        };
+        format!("{}: {}", location, diagnostic)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.key.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.key, self.message)
+        }
+    }
+}
+
+/// Collects diagnostics while the checks run
+struct Validator<'a> {
+    source: &'a str,
+    /// Keys set from the command line or environment, which have no location in the file
+    overridden: &'a [String],
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn push(&mut self, severity: Severity, key: &str, mut message: String) {
+        let location = if self.overridden.iter().any(|k| k == key) {
+            message.push_str(" (set by override)");
+            None
+        } else {
+            locate(self.source, key)
+        };
+
+        self.diagnostics.push(Diagnostic {
+            severity,
+            key: key.to_string(),
+            location,
+            message,
+        });
+    }
+
+    fn error(&mut self, key: &str, message: String) {
+        self.push(Severity::Error, key, message);
+    }
+
+    fn warning(&mut self, key: &str, message: String) {
+        self.push(Severity::Warning, key, message);
+    }
+
+    /// Checks the settings of an `api` section; hotkeys are checked separately
+    fn api(&mut self, prefix: &str, api: &ApiConfig) {
+        let url = self.url(&format!("{}.url", prefix), &api.url);
+        self.model(&format!("{}.model", prefix), &api.model, api.backend, url.as_ref());
+        self.range(&format!("{}.temperature", prefix), api.temperature, 0.0, 1.0);
+        self.range(&format!("{}.temperature_inc", prefix), api.temperature_inc, 0.0, 1.0);
+    }
+
+    fn url(&mut self, key: &str, value: &str) -> Option<Url> {
+        if !value.starts_with("http://") && !value.starts_with("https://") {
+            self.error(key, format!("URL '{}' must start with http:// or https://", value));
+            return None;
+        }
+
+        match Url::parse(value) {
+            Ok(url) if url.host_str().is_some() => Some(url),
+            Ok(_) => {
+                self.error(key, format!("URL '{}' has no host", value));
+                None
+            },
+            Err(e) => {
+                self.error(key, format!("invalid URL '{}': {}", value, e));
+                None
+            },
+        }
+    }
+
+    /// Checks the sinks of an `output` section
+    fn output(&mut self, prefix: &str, output: &OutputConfig) {
+        self.sinks(&format!("{}.sinks", prefix), &output.sinks);
+    }
+
+    /// Checks the spoken commands of a `postprocess` section
+    fn postprocess(&mut self, prefix: &str, postprocess: &PostProcessConfig) {
+        for phrase in postprocess.commands.keys() {
+            if !phrase.chars().any(char::is_alphanumeric) {
+                self.error(&format!("{}.commands.{}", prefix, phrase), "a command phrase needs at least one word".to_string());
+            }
+        }
+        if postprocess.spoken_commands && spoken::is_command(&postprocess.escape_phrase, postprocess) {
+            self.error(&format!("{}.escape_phrase", prefix), format!("'{}' is itself a command", postprocess.escape_phrase));
+        }
+    }
+
+    fn sinks(&mut self, prefix: &str, sinks: &[SinkConfig]) {
+        for (index, sink) in sinks.iter().enumerate() {
+            let prefix = format!("{}.{}", prefix, index);
+            match sink {
+                SinkConfig::File { path, .. } | SinkConfig::Socket { path } | SinkConfig::Fifo { path } => {
+                    let key = format!("{}.path", prefix);
+                    if path.as_os_str().is_empty() {
+                        self.error(&key, "path must not be empty".to_string());
+                        continue;
+                    }
+                    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+                    if !parent.is_dir() {
+                        self.warning(&key, format!("directory {} doesn't exist", parent.display()));
+                    }
+                },
+                SinkConfig::Webhook { url, timeout_ms } => {
+                    self.url(&format!("{}.url", prefix), url);
+                    self.max_ms(&format!("{}.timeout_ms", prefix), *timeout_ms, 60_000);
+                },
+                SinkConfig::Paste | SinkConfig::Type | SinkConfig::Clipboard | SinkConfig::Stdout => {},
+            }
+        }
+    }
+
+    fn hotkey(&mut self, key: &str, value: &str) -> Option<HotKey> {
+        match HotKey::from_str(value) {
+            Ok(hotkey) => Some(hotkey),
+            Err(e) => {
+                self.error(key, format!("invalid hotkey '{}': {}", value, e));
+                None
+            },
+        }
+    }
+
+    fn range(&mut self, key: &str, value: f32, min: f32, max: f32) {
+        if !(min..=max).contains(&value) {
+            self.error(key, format!("{} is out of range, expected {} to {}", value, min, max));
+        }
+    }
+
+    fn max_ms(&mut self, key: &str, value: u64, max: u64) {
+        if value > max {
+            self.error(key, format!("{} ms is too long, expected at most {} ms", value, max));
+        }
+    }
+
+    fn model(&mut self, key: &str, value: &str, backend: Backend, url: Option<&Url>) {
+        // whisper.cpp's server ignores the model
+        if backend == Backend::WhisperCpp {
+            return;
+        }
+
+        if value.trim().is_empty() {
+            self.error(key, "model must not be empty".to_string());
+            return;
+        }
+
+        // Local servers accept any model name, so only check against OpenAI's list
+        let is_openai = url.and_then(|u| u.host_str()) == Some("api.openai.com");
+        if is_openai && !KNOWN_OPENAI_MODELS.contains(&value) {
+            self.warning(key, format!("unknown model '{}', known models are: {}", value, KNOWN_OPENAI_MODELS.join(", ")));
+        }
+    }
+}
+
+/// Runs every check against an already parsed config. `source` is the YAML text the
+/// config came from and is only used to find line and column numbers.
+pub fn validate(config: &Config, source: &str, overridden: &[String]) -> Vec<Diagnostic> {
+    let mut v = Validator { source, overridden, diagnostics: Vec::new() };
+
+    v.api("api", &config.api);
+    v.postprocess("postprocess", &config.postprocess);
+    v.output("output", &config.output);
+    if !(1..=16).contains(&config.transcription.workers) {
+        v.error("transcription.workers", format!("{} is out of range, expected 1 to 16", config.transcription.workers));
+    }
+    v.max_ms("transcription.late_after_ms", config.transcription.late_after_ms, 600_000);
+    v.sinks("transcription.late_sinks", &config.transcription.late_sinks);
+    if config.listener.backend == HotkeyBackend::Evdev && !cfg!(target_os = "linux") {
+        v.error("listener.backend", "evdev only works on Linux".to_string());
+    }
+    for (index, device) in config.listener.devices.iter().enumerate() {
+        if !device.exists() {
+            v.warning(&format!("listener.devices.{}", index), format!("{} doesn't exist", device.display()));
+        }
+    }
+    v.range("notifications.low_confidence_threshold", config.notifications.low_confidence_threshold, 0.0, 1.0);
+    v.range("cues.volume", config.cues.volume, 0.0, 1.0);
+    v.max_ms("gestures.double_tap_ms", config.gestures.double_tap_ms, 2_000);
+    v.max_ms("gestures.long_press_ms", config.gestures.long_press_ms, 5_000);
+    v.range("hands_free.threshold", config.hands_free.threshold, 0.0, 1.0);
+    v.max_ms("hands_free.silence_ms", config.hands_free.silence_ms, 30_000);
+    let cue_files = [
+        ("cues.start", &config.cues.start),
+        ("cues.stop", &config.cues.stop),
+        ("cues.cancel", &config.cues.cancel),
+        ("cues.success", &config.cues.success),
+        ("cues.error", &config.cues.error),
+    ];
+    for (key, path) in cue_files {
+        if let Some(path) = path.as_ref().filter(|p| !p.exists()) {
+            v.warning(key, format!("{} doesn't exist, the built-in tone is played instead", path.display()));
+        }
+    }
+    v.max_ms("clipboard.paste_delay_ms", config.clipboard.paste_delay_ms, 10_000);
+    v.max_ms("clipboard.restore_delay_ms", config.clipboard.restore_delay_ms, 10_000);
+    v.max_ms("typing.start_delay_ms", config.typing.start_delay_ms, 10_000);
+    v.max_ms("typing.char_delay_ms", config.typing.char_delay_ms, 1_000);
+    v.max_ms("typing.chunk_delay_ms", config.typing.chunk_delay_ms, 1_000);
+    if config.typing.chunk_size == 0 {
+        v.error("typing.chunk_size", "must be at least 1".to_string());
+    }
+
+    for (index, rule) in config.paste.rules.iter().enumerate() {
+        let prefix = format!("paste.rules.{}", index);
+        if rule.class.is_none() && rule.title.is_none() {
+            v.error(&prefix, "rule needs a class or title to match".to_string());
+        }
+        if let PasteStrategy::Keys(keys) = &rule.paste {
+            if let Err(e) = keys.parse::<Chord>() {
+                v.error(&format!("{}.paste", prefix), format!("invalid paste '{}': {}, expected a key combination, type, primary or clipboard_only", keys, e));
+            }
+        }
+    }
+
+    // Check only the values a profile sets, so problems are reported where they are
+    for (name, profile) in &config.profiles {
+        let prefix = format!("profiles.{}", name);
+        if name == DEFAULT_PROFILE {
+            v.error(&prefix, format!("'{}' is reserved for the top level settings", DEFAULT_PROFILE));
+            continue;
+        }
+
+        let Some(resolved) = config.profile(name) else {
+            continue;
+        };
+
+        let url = match &profile.url {
+            Some(url) => v.url(&format!("{}.url", prefix), url),
+            None => Url::parse(&resolved.api.url).ok(),
+        };
+        if profile.model.is_some() || profile.url.is_some() || profile.backend.is_some() {
+            v.model(&format!("{}.model", prefix), &resolved.api.model, resolved.api.backend, url.as_ref());
+        }
+        if let Some(temperature) = profile.temperature {
+            v.range(&format!("{}.temperature", prefix), temperature, 0.0, 1.0);
+        }
+        if let Some(temperature_inc) = profile.temperature_inc {
+            v.range(&format!("{}.temperature_inc", prefix), temperature_inc, 0.0, 1.0);
+        }
+        // Laid over the defaults rather than the top level, so only the profile's own
+        // values are checked
+        if profile.postprocess.is_some() {
+            let key = format!("{}.postprocess", prefix);
+            match overlay(&PostProcessConfig::default(), profile.postprocess.as_ref()) {
+                Ok(postprocess) => v.postprocess(&key, &postprocess),
+                Err(e) => v.error(&key, e.to_string()),
+            }
+        }
+        if profile.output.is_some() {
+            let key = format!("{}.output", prefix);
+            match overlay(&OutputConfig::default(), profile.output.as_ref()) {
+                Ok(output) => v.output(&key, &output),
+                Err(e) => v.error(&key, e.to_string()),
+            }
+        }
+    }
+
+    // Every hotkey must parse and each key combination can only have one action
+    let mut seen: Vec<(HotKey, HotkeyBinding)> = Vec::new();
+    for binding in config.hotkey_bindings() {
+        let Some(hotkey) = v.hotkey(&binding.key, &binding.hotkey) else {
+            continue;
+        };
+        match seen.iter().find(|(h, _)| *h == hotkey) {
+            Some((_, other)) if other.actions == binding.actions => {
+                v.warning(&binding.key, format!("hotkey '{}' is also set by {}", binding.hotkey, other.key));
+            },
+            Some((_, other)) => {
+                v.error(&binding.key, format!("hotkey '{}' is already bound to {} by {}", binding.hotkey, other.actions, other.key));
+            },
+            None => seen.push((hotkey, binding)),
+        }
+    }
+    for (hotkey, actions) in &config.hotkeys {
+        let key = format!("hotkeys.{}", hotkey);
+        for action in actions.all() {
+            if let HotkeyAction::Toggle(Some(profile)) | HotkeyAction::Hold(Some(profile)) | HotkeyAction::HandsFree(Some(profile)) = action {
+                if config.profile(profile).is_none() {
+                    v.error(&key, format!("unknown profile '{}'", profile));
+                }
+            }
+        }
+        // A tap or double tap has ended by the time it fires, so there's no release to stop on
+        if let HotkeyActions::Gestures(gestures) = actions {
+            let released = [("tap", &gestures.tap), ("double_tap", &gestures.double_tap)];
+            for (gesture, action) in released {
+                if let Some(HotkeyAction::Hold(_)) = action {
+                    v.error(&format!("{}.{}", key, gesture), "hold only works on its own or as a long_press".to_string());
+                }
+            }
+        }
+    }
+
+    if config.profile(&config.retranscribe.profile).is_none() {
+        v.error("retranscribe.profile", format!("unknown profile '{}'", config.retranscribe.profile));
+    }
+
+    if let UndoMethod::Keys(keys) = &config.undo.method {
+        if let Err(e) = keys.parse::<Chord>() {
+            v.error("undo.method", format!("invalid method '{}': {}, expected backspace or a key combination", keys, e));
+        }
+    }
+
+    v.diagnostics
+}
+
+/// Returns the keys the overrides set, so their diagnostics don't point into the file
+fn overridden_keys(source: &str, overrides: &Overrides) -> Result<Vec<String>> {
+    let mut value: serde_yaml::Value = serde_yaml::from_str(source)?;
+    overrides.apply(&mut value)
+}
+
+/// Validates an already loaded config against the file it was loaded from
+pub fn check(path: &Path, config: &Config, overrides: &Overrides) -> Result<Report> {
+    let source = std::fs::read_to_string(path)
+        .context(format!("Failed to read config file: {}", path.display()))?;
+    let overridden = overridden_keys(&source, overrides)?;
+
+    Ok(Report {
+        path: path.to_path_buf(),
+        diagnostics: validate(config, &source, &overridden),
+    })
+}
+
+/// Parses and validates a config file without creating it or starting anything.
+/// Parse errors are reported as diagnostics rather than returned as errors.
+pub fn check_file(path: &Path, overrides: &Overrides) -> Result<Report> {
+    let source = std::fs::read_to_string(path)
+        .context(format!("Failed to read config file: {}", path.display()))?;
+
+    let diagnostics = match Config::parse(&source) {
+        Ok(_) => match Config::parse_with_overrides(&source, overrides) {
+            Ok(config) => validate(&config, &source, &overridden_keys(&source, overrides)?),
+            Err(e) => vec![Diagnostic {
+                severity: Severity::Error,
+                key: String::new(),
+                location: None,
+                message: format!("{} (set by override)", strip_location(e.to_string())),
+            }],
+        },
+        Err(e) => vec![Diagnostic {
+            severity: Severity::Error,
+            key: String::new(),
+            location: e.location().map(|l| (l.line(), l.column())),
+            message: strip_location(e.to_string()),
+        }],
+    };
+
+    Ok(Report {
+        path: path.to_path_buf(),
+        diagnostics,
+    })
+}
+
+/// Checks rules.yaml against the config. Rules for a profile the config doesn't have
+/// never run, which is most likely a typo.
+pub fn check_rules(path: &Path, rules: &Rules, config: &Config) -> Result<Report> {
+    let source = std::fs::read_to_string(path)
+        .context(format!("Failed to read rules file: {}", path.display()))?;
+
+    let mut v = Validator { source: &source, overridden: &[], diagnostics: Vec::new() };
+    for name in rules.profile_names() {
+        if config.profile(name).is_none() {
+            v.warning(&format!("profiles.{}", name), format!("No profile named '{}' in the config, these rules never run", name));
+        }
+    }
+
+    Ok(Report {
+        path: path.to_path_buf(),
+        diagnostics: v.diagnostics,
+    })
+}
+
+/// serde_yaml appends " at line X column Y" to its messages, which we already show
+fn strip_location(mut message: String) -> String {
+    if let Some(index) = message.rfind(" at line ") {
+        message.truncate(index);
+    }
+    message
+}
+
+/// Finds the 1-based line and column of the value for a dotted key path such as
+/// "api.temperature". This understands the block style used by config.yaml
+/// (nested mappings, "- " sequence items and | or > block scalars), which is all we
+/// need for diagnostics. Returns None rather than a guess when the path can't be found
+/// exactly, for example when a key contains a dot.
+pub fn locate(source: &str, key_path: &str) -> Option<(usize, usize)> {
+    let wanted: Vec<&str> = key_path.split('.').collect();
+    // Stack of (indent, path segment) for the mapping we are currently inside
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    // Next sequence index per parent path
+    let mut seq_counters: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    // Indent of the line that started the block scalar we are inside, if any
+    let mut block_scalar: Option<usize> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(block_indent) = block_scalar {
+            if trimmed.is_empty() || line.len() - trimmed.len() > block_indent {
+                continue;
+            }
+            block_scalar = None;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("---") {
+            continue;
+        }
+
+        let mut indent = line.len() - trimmed.len();
+        let mut content = trimmed;
+
+        while stack.last().is_some_and(|(i, _)| *i >= indent) {
+            stack.pop();
+        }
+
+        // Sequence item: push its index, then treat the rest of the line as a nested key
+        if let Some(rest) = content.strip_prefix("- ").or(if content == "-" { Some("") } else { None }) {
+            let parent = join(&stack);
+            let counter = seq_counters.entry(parent).or_insert(0);
+            stack.push((indent, counter.to_string()));
+            *counter += 1;
+
+            if is_path(&stack, &wanted) {
+                return Some((line_index + 1, indent + 3));
+            }
+            if starts_block_scalar(rest) {
+                block_scalar = Some(indent);
+                continue;
+            }
+
+            let offset = content.len() - rest.len();
+            indent += offset;
+            content = rest;
+            if content.trim().is_empty() {
+                continue;
+            }
+        }
+
+        let Some((key, value)) = split_key(content) else {
+            continue;
+        };
+
+        stack.push((indent, key.to_string()));
+        if starts_block_scalar(value) {
+            block_scalar = Some(indent);
+        }
+        if is_path(&stack, &wanted) {
+            let value_offset = content.len() - value.len();
+            let column = if value.trim().is_empty() {
+                indent + 1
+            } else {
+                indent + value_offset + (value.len() - value.trim_start().len()) + 1
+            };
+            return Some((line_index + 1, column));
+        }
+    }
+
+    None
+}
+
+/// Splits "key: value" into its parts, stripping quotes around the key
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    let colon = if content.starts_with('"') || content.starts_with('\'') {
+        let quote = &content[..1];
+        let end = content[1..].find(quote)? + 1;
+        end + content[end..].find(':')?
+    } else {
+        content.find(": ").or_else(|| content.strip_suffix(':').map(|s| s.len()))?
+    };
+
+    let key = content[..colon].trim().trim_matches(|c| c == '"' || c == '\'');
+    Some((key, &content[colon + 1..]))
+}
+
+/// Whether a value starts a block scalar, such as "|", ">-" or "|2 # comment"
+fn starts_block_scalar(value: &str) -> bool {
+    let value = value.split(" #").next().unwrap_or("").trim();
+    value.strip_prefix('|').or_else(|| value.strip_prefix('>'))
+        .is_some_and(|indicators| indicators.chars().all(|c| c == '+' || c == '-' || c.is_ascii_digit()))
+}
+
+// Compares segment by segment, so a key containing a dot never matches
+fn is_path(stack: &[(usize, String)], wanted: &[&str]) -> bool {
+    stack.len() == wanted.len() && stack.iter().zip(wanted).all(|((_, segment), wanted)| segment == wanted)
+}
+
+fn join(stack: &[(usize, String)]) -> String {
+    stack.iter().map(|(_, s)| s.as_str()).collect::<Vec<_>>().join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_CONFIG;
+
+    /// The settings every config needs, on lines 1 to 7
+    const API: &str = "api:
+  url: https://api.openai.com/v1/audio/transcriptions
+  hotkey: ctrl+shift+space
+  model: whisper-1
+  prompt: ''
+  temperature: 0.0
+  temperature_inc: 0.2
+";
+
+    fn diagnostics(source: &str) -> Vec<Diagnostic> {
+        validate(&Config::parse(source).unwrap(), source, &[])
+    }
+
+    fn find<'a>(found: &'a [Diagnostic], key: &str) -> &'a Diagnostic {
+        found.iter().find(|d| d.key == key).unwrap_or_else(|| panic!("no diagnostic for {} in {:?}", key, found))
+    }
+
+    #[test]
+    fn default_config_is_clean() {
+        let errors: Vec<String> = diagnostics(DEFAULT_CONFIG).iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn errors_point_at_the_value() {
+        let source = format!("{}transcription:\n  workers: 0\n", API.replace("temperature: 0.0", "temperature: 3.0"));
+        let found = diagnostics(&source);
+
+        let temperature = find(&found, "api.temperature");
+        assert_eq!(temperature.severity, Severity::Error);
+        assert_eq!(temperature.location, Some((6, 16)));
+        assert_eq!(find(&found, "transcription.workers").location, Some((9, 12)));
+    }
+
+    #[test]
+    fn unknown_model_is_a_warning() {
+        let found = diagnostics(&API.replace("whisper-1", "whisper-2"));
+        let model = find(&found, "api.model");
+        assert_eq!(model.severity, Severity::Warning);
+        assert!(model.message.contains("whisper-2"));
+    }
+
+    #[test]
+    fn overridden_values_have_no_location() {
+        let source = API.replace("temperature: 0.0", "temperature: 3.0");
+        let found = validate(&Config::parse(&source).unwrap(), &source, &["api.temperature".to_string()]);
+        let temperature = find(&found, "api.temperature");
+        assert_eq!(temperature.location, None);
+        assert!(temperature.message.ends_with("(set by override)"));
+    }
+
+    #[test]
+    fn profile_problems_are_reported_in_the_profile() {
+        let found = diagnostics(&format!("{}profiles:\n  default:\n    model: whisper-1\n  notes:\n    temperature: 2.0\n", API));
+        assert_eq!(find(&found, "profiles.default").severity, Severity::Error);
+        let temperature = find(&found, "profiles.notes.temperature");
+        assert_eq!(temperature.severity, Severity::Error);
+        assert_eq!(temperature.location, Some((12, 18)));
+    }
+
+    #[test]
+    fn parse_errors_are_diagnostics() {
+        let path = std::env::temp_dir().join(format!("talky-validate-{}.yaml", std::process::id()));
+        std::fs::write(&path, API.replace("temperature: 0.0", "temperature: warm")).unwrap();
+        let report = check_file(&path, &Overrides::default()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(report.has_errors());
+        assert_eq!(report.diagnostics[0].location.map(|(line, _)| line), Some(6));
+        assert!(!report.diagnostics[0].message.contains(" at line "));
+    }
+
+    #[test]
+    fn locates_nested_keys_and_list_items() {
+        let source = "paste:\n  rules:\n    - class: firefox\n      paste: ctrl+shift+v\n    - title: vim\n";
+        assert_eq!(locate(source, "paste.rules.0.paste"), Some((4, 14)));
+        assert_eq!(locate(source, "paste.rules.1.title"), Some((5, 14)));
+        assert_eq!(locate(source, "paste.missing"), None);
+    }
+
+    #[test]
+    fn skips_block_scalars() {
+        let source = "prompt: |\n  api:\n    model: fake\n  - x: y\napi:\n  model: real\n";
+        assert_eq!(locate(source, "api.model"), Some((6, 10)));
+        assert_eq!(locate(source, "prompt.api.model"), None);
+        assert_eq!(locate(source, "prompt"), Some((1, 9)));
+    }
+
+    #[test]
+    fn keys_with_dots_are_not_guessed() {
+        let source = "profiles:\n  v1.5:\n    api:\n      model: x\n  v1:\n    5:\n      api: y\n";
+        assert_eq!(locate(source, "profiles.v1.5.api"), Some((7, 12)));
+        assert_eq!(locate(source, "profiles.v1.5.api.model"), None);
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::Result;
+
+/// The focused window, used to pick a paste strategy
+#[derive(Debug, Clone, Default)]
+pub struct ActiveWindow {
+    /// WM_CLASS instance and class names, e.g. "gnome-terminal-server" and "Gnome-terminal"
+    pub instance: String,
+    pub class: String,
+    pub title: String,
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::ActiveWindow;
+    use anyhow::Result;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::*;
+    use x11rb::protocol::Event;
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+    use log::*;
+
+    /// How long we keep serving the primary selection after taking it
+    const PRIMARY_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn intern(conn: &RustConnection, name: &[u8]) -> Result<Atom> {
+        Ok(conn.intern_atom(false, name)?.reply()?.atom)
+    }
+
+    fn string_property(conn: &RustConnection, window: Window, property: Atom, kind: Atom) -> Result<Vec<u8>> {
+        Ok(conn.get_property(false, window, property, kind, 0, 1024)?.reply()?.value)
+    }
+
+    pub fn active_window() -> Result<Option<ActiveWindow>> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let net_active_window = intern(&conn, b"_NET_ACTIVE_WINDOW")?;
+        let reply = conn.get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)?.reply()?;
+        let Some(window) = reply.value32().and_then(|mut v| v.next()).filter(|w| *w != NONE) else {
+            return Ok(None);
+        };
+
+        // WM_CLASS is "instance\0class\0"
+        let wm_class = string_property(&conn, window, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?;
+        let mut names = wm_class.split(|b| *b == 0).map(|s| String::from_utf8_lossy(s).into_owned());
+        let instance = names.next().unwrap_or_default();
+        let class = names.next().unwrap_or_default();
+
+        let net_wm_name = intern(&conn, b"_NET_WM_NAME")?;
+        let utf8_string = intern(&conn, b"UTF8_STRING")?;
+        let mut title = string_property(&conn, window, net_wm_name, utf8_string)?;
+        if title.is_empty() {
+            title = string_property(&conn, window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?;
+        }
+
+        Ok(Some(ActiveWindow {
+            instance,
+            class,
+            title: String::from_utf8_lossy(&title).into_owned(),
+        }))
+    }
+
+    pub fn set_primary(text: &str) -> Result<()> {
+        let (conn, screen_num) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        // Selections are owned by a window, so make an invisible one
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT, window, root, 0, 0, 1, 1, 0,
+            WindowClass::INPUT_OUTPUT, 0, &CreateWindowAux::new(),
+        )?;
+
+        conn.set_selection_owner(window, u32::from(AtomEnum::PRIMARY), CURRENT_TIME)?;
+        if conn.get_selection_owner(u32::from(AtomEnum::PRIMARY))?.reply()?.owner != window {
+            return Err(anyhow::anyhow!("Failed to take ownership of the primary selection"));
+        }
+
+        let targets = intern(&conn, b"TARGETS")?;
+        let utf8_string = intern(&conn, b"UTF8_STRING")?;
+        let text = text.to_string();
+
+        // The selection only exists while we answer requests for it
+        thread::spawn(move || {
+            if let Err(e) = serve_primary(&conn, window, &text, targets, utf8_string) {
+                error!("Error serving the primary selection: {:?}", e);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn serve_primary(conn: &RustConnection, window: Window, text: &str, targets: Atom, utf8_string: Atom) -> Result<()> {
+        let deadline = Instant::now() + PRIMARY_TIMEOUT;
+
+        while Instant::now() < deadline {
+            let Some(event) = conn.poll_for_event()? else {
+                thread::sleep(Duration::from_millis(20));
+                continue;
+            };
+
+            match event {
+                Event::SelectionRequest(request) => {
+                    // Old clients leave the property empty and expect the target to be used
+                    let property = if request.property == NONE { request.target } else { request.property };
+
+                    let property = if request.target == targets {
+                        let supported = [targets, utf8_string, AtomEnum::STRING.into()];
+                        conn.change_property32(PropMode::REPLACE, request.requestor, property, AtomEnum::ATOM, &supported)?;
+                        property
+                    } else if request.target == utf8_string || request.target == u32::from(AtomEnum::STRING) {
+                        conn.change_property8(PropMode::REPLACE, request.requestor, property, request.target, text.as_bytes())?;
+                        property
+                    } else {
+                        NONE
+                    };
+
+                    let notify = SelectionNotifyEvent {
+                        response_type: SELECTION_NOTIFY_EVENT,
+                        sequence: 0,
+                        time: request.time,
+                        requestor: request.requestor,
+                        selection: request.selection,
+                        target: request.target,
+                        property,
+                    };
+                    conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify)?;
+                    conn.flush()?;
+                },
+                // Something else took the selection
+                Event::SelectionClear(_) => break,
+                _ => {},
+            }
+        }
+
+        conn.destroy_window(window)?;
+        conn.flush()?;
+        Ok(())
+    }
+}
+
+/// Returns the focused window, or None if it can't be determined (no X11, or no focus)
+pub fn active_window() -> Option<ActiveWindow> {
+    #[cfg(target_os = "linux")]
+    {
+        match imp::active_window() {
+            Ok(window) => window,
+            Err(e) => {
+                log::debug!("Couldn't get the active window: {:?}", e);
+                None
+            },
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Puts text in the X11 primary selection, which is what middle click and Shift+Insert
+/// paste in many terminals. The selection is served from a background thread for a while.
+pub fn set_primary(text: &str) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        imp::set_primary(text)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = text;
+        Err(anyhow::anyhow!("The primary selection is only available on X11"))
+    }
+}